@@ -108,6 +108,20 @@ impl<'a> CliCommand<'a> for BuildSubcommand {
                 .parse::<cron::Schedule>()
                 .map_err(|_| format_err!("Could not parse cron schedule: {}", schedule))?;
         }
+        // Validate container image build contexts
+        for image_name in lucky_metadata.container_images.keys() {
+            let dockerfile = charm_path
+                .join("container_images")
+                .join(image_name)
+                .join("Dockerfile");
+            if !dockerfile.is_file() {
+                anyhow::bail!(
+                    "Could not find Dockerfile for container image \"{}\": {:?}",
+                    image_name,
+                    dockerfile
+                );
+            }
+        }
 
         // Clear the target directory
         if target_dir.exists() {
@@ -8,6 +8,7 @@ mod get_config;
 mod get_resource;
 mod kv;
 mod leader;
+mod network;
 mod port;
 mod private_address;
 mod public_address;
@@ -53,6 +54,7 @@ impl<'a> CliCommand<'a> for ClientSubcommand {
             Box::new(leader::LeaderSubcommand),
             Box::new(random::RandomSubcommand),
             Box::new(get_resource::GetResourceSubcommand),
+            Box::new(network::NetworkSubcommand),
         ]
     }
 
@@ -3,13 +3,32 @@ use clap::{App, Arg, ArgMatches};
 use crate::cli::*;
 
 mod apply_updates;
+mod capability;
+mod cp;
 mod delete;
+mod depends_on;
 mod env;
+mod exec;
+mod extra_host;
 mod image;
+mod init;
+mod label;
+mod limits;
+mod logs;
+mod network;
 mod port;
+mod registry;
+mod set_auto_expose;
 mod set_command;
 mod set_entrypoint;
+mod set_hostname;
 mod set_network;
+mod set_privileged;
+mod set_shm_size;
+mod set_update_strategy;
+mod set_user;
+mod set_workdir;
+mod stats;
 mod volume;
 
 pub(super) struct ContainerSubcommand;
@@ -35,8 +54,27 @@ impl<'a> CliCommand<'a> for ContainerSubcommand {
             Box::new(set_command::SetCommandSubcommand),
             Box::new(volume::VolumeSubcommand),
             Box::new(delete::DeleteSubcommand),
+            Box::new(exec::ExecSubcommand),
+            Box::new(cp::CpSubcommand),
             Box::new(port::PortSubcommand),
             Box::new(set_network::SetNetworkSubcommand),
+            Box::new(limits::LimitsSubcommand),
+            Box::new(set_user::SetUserSubcommand),
+            Box::new(set_workdir::SetWorkdirSubcommand),
+            Box::new(set_hostname::SetHostnameSubcommand),
+            Box::new(set_privileged::SetPrivilegedSubcommand),
+            Box::new(set_shm_size::SetShmSizeSubcommand),
+            Box::new(set_update_strategy::SetUpdateStrategySubcommand),
+            Box::new(set_auto_expose::SetAutoExposeSubcommand),
+            Box::new(label::LabelSubcommand),
+            Box::new(capability::CapabilitySubcommand),
+            Box::new(extra_host::ExtraHostSubcommand),
+            Box::new(logs::LogsSubcommand),
+            Box::new(stats::StatsSubcommand),
+            Box::new(registry::RegistrySubcommand),
+            Box::new(network::NetworkSubcommand),
+            Box::new(depends_on::DependsOnSubcommand),
+            Box::new(init::InitSubcommand),
         ]
     }
 
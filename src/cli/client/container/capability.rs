@@ -0,0 +1,175 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct CapabilitySubcommand;
+
+impl<'a> CliCommand<'a> for CapabilitySubcommand {
+    fn get_name(&self) -> &'static str {
+        "capability"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add and remove container Linux capabilities")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(AddSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_capability",
+            content: include_str!("cli_help/capability.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct AddSubcommand;
+
+impl<'a> CliCommand<'a> for AddSubcommand {
+    fn get_name(&self) -> &'static str {
+        "add"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add a capability")
+            .arg(Arg::with_name("capability")
+                .help("The capability to add such as `NET_ADMIN`")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let capability = args
+            .value_of("capability")
+            .expect("Missing required argument: capability");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_capability_add(capability.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Remove a capability")
+            .arg(Arg::with_name("capability")
+                .help("The capability to remove")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let capability = args
+            .value_of("capability")
+            .expect("Missing required argument: capability");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_capability_remove(capability.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get a list of the capabilities added to the container")
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for capability in client
+            .container_capability_get_all(container.map(Into::into))
+            .call()?
+            .capabilities
+        {
+            writeln!(std::io::stdout(), "{}", capability)?;
+        }
+
+        Ok(data)
+    }
+}
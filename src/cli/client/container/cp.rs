@@ -0,0 +1,108 @@
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct CpSubcommand;
+
+impl<'a> CliCommand<'a> for CpSubcommand {
+    fn get_name(&self) -> &'static str {
+        "cp"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Copy files and directories into and out of a container")
+            .long_about(concat!(
+                "Copy files and directories into and out of a container. Paths in the container ",
+                "are written as `container_name:path`, where an empty container name, such as in ",
+                "`:/etc/app.conf`, is the default container."))
+            .arg(Arg::with_name("source")
+                .help("The file or directory to copy")
+                .required(true))
+            .arg(Arg::with_name("target")
+                .help("The path to copy the file or directory to")
+                .required(true))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_cp",
+            content: include_str!("cli_help/cp.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let source = args
+            .value_of("source")
+            .expect("Missing required argument: source");
+        let target = args
+            .value_of("target")
+            .expect("Missing required argument: target");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        match (parse_container_path(source), parse_container_path(target)) {
+            // Copy from the host to the container
+            (None, Some((container, target))) => {
+                client
+                    .container_copy_to(host_path(source)?, target.into(), container)
+                    .call()?;
+            }
+            // Copy from the container to the host
+            (Some((container, source)), None) => {
+                client
+                    .container_copy_from(source.into(), host_path(target)?, container)
+                    .call()?;
+            }
+            _ => anyhow::bail!(
+                "Exactly one of the source and target must be a container path, such as `:/path`"
+            ),
+        }
+
+        Ok(data)
+    }
+}
+
+/// Parse a path in the form `container_name:path` into the container name and the path
+///
+/// Returns `None` if the path is not a container path. An empty container name is the default
+/// container.
+fn parse_container_path(path: &str) -> Option<(Option<String>, &str)> {
+    let separator = path.find(':')?;
+
+    // Host paths such as `./file:name` are not container paths
+    let container_name = &path[..separator];
+    if container_name.contains('/') {
+        return None;
+    }
+
+    Some((
+        if container_name.is_empty() {
+            None
+        } else {
+            Some(container_name.into())
+        },
+        &path[separator + 1..],
+    ))
+}
+
+/// Get the absolute path to a path on the host because the daemon has a different working dir
+fn host_path(path: &str) -> anyhow::Result<String> {
+    Ok(std::env::current_dir()
+        .context("Could not get current directory")?
+        .join(path)
+        .to_string_lossy()
+        .into_owned())
+}
@@ -0,0 +1,193 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct DependsOnSubcommand;
+
+impl<'a> CliCommand<'a> for DependsOnSubcommand {
+    fn get_name(&self) -> &'static str {
+        "depends-on"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add and remove dependencies on other containers")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(AddSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_depends_on",
+            content: include_str!("cli_help/depends_on.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct AddSubcommand;
+
+impl<'a> CliCommand<'a> for AddSubcommand {
+    fn get_name(&self) -> &'static str {
+        "add"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Make the container depend on another container")
+            .arg(Arg::with_name("dependency")
+                .help("The name of the container to depend on")
+                .required(true))
+            .arg(Arg::with_name("wait_for_healthy")
+                .help("Wait for the dependency to be healthy before starting the container")
+                .long("wait-for-healthy")
+                .short('w'))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let dependency = args
+            .value_of("dependency")
+            .expect("Missing required argument: dependency");
+        let wait_for_healthy = args.is_present("wait_for_healthy");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_dependency_add(
+                dependency.into(),
+                wait_for_healthy,
+                container.map(Into::into),
+            )
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Remove a dependency from the container")
+            .arg(Arg::with_name("dependency")
+                .help("The name of the container to remove from the dependencies")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let dependency = args
+            .value_of("dependency")
+            .expect("Missing required argument: dependency");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_dependency_remove(dependency.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get a list of the containers that the container depends on")
+            .long_about(concat!(
+                "Get a list of the containers that the container depends on. Dependencies will be ",
+                "printed out, one per line, followed by `(wait-for-healthy)` if the container ",
+                "waits for the dependency to be healthy."
+            ))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for dependency in client
+            .container_dependency_get_all(container.map(Into::into))
+            .call()?
+            .dependencies
+        {
+            if dependency.wait_for_healthy {
+                writeln!(std::io::stdout(), "{} (wait-for-healthy)", dependency.name)?;
+            } else {
+                writeln!(std::io::stdout(), "{}", dependency.name)?;
+            }
+        }
+
+        Ok(data)
+    }
+}
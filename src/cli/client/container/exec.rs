@@ -0,0 +1,72 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct ExecSubcommand;
+
+impl<'a> CliCommand<'a> for ExecSubcommand {
+    fn get_name(&self) -> &'static str {
+        "exec"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Run a command in a running container")
+            .long_about(concat!(
+                "Run a command in a running container. The output of the command will be printed ",
+                "and Lucky will exit with the exit code of the command."))
+            .setting(AppSettings::TrailingVarArg)
+            .arg(Arg::with_name("command")
+                .help("The command to run")
+                .multiple(true)
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_exec",
+            content: include_str!("cli_help/exec.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let command = args
+            .values_of("command")
+            .expect("Missing required argument: command")
+            .map(Into::into)
+            .collect();
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Run the command
+        let output = client
+            .container_exec(command, container.map(Into::into))
+            .call()?;
+
+        // Print the command output
+        write!(std::io::stdout(), "{}", output.stdout)?;
+        write!(std::io::stderr(), "{}", output.stderr)?;
+
+        // Exit with the exit code of the command
+        if output.exit_code != 0 {
+            return Err(CliError::Exit(output.exit_code as i32).into());
+        }
+
+        Ok(data)
+    }
+}
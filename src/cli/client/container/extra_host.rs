@@ -0,0 +1,183 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct ExtraHostSubcommand;
+
+impl<'a> CliCommand<'a> for ExtraHostSubcommand {
+    fn get_name(&self) -> &'static str {
+        "extra-host"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add and remove entries in the container's /etc/hosts file")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(AddSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_extra-host",
+            content: include_str!("cli_help/extra_host.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct AddSubcommand;
+
+impl<'a> CliCommand<'a> for AddSubcommand {
+    fn get_name(&self) -> &'static str {
+        "add"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add a host entry")
+            .arg(Arg::with_name("hostname")
+                .help("The hostname to add")
+                .required(true))
+            .arg(Arg::with_name("ip")
+                .help("The IP address that the hostname should resolve to")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let hostname = args
+            .value_of("hostname")
+            .expect("Missing required argument: hostname");
+        let ip = args.value_of("ip").expect("Missing required argument: ip");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_extra_host_add(hostname.into(), ip.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Remove a host entry")
+            .arg(Arg::with_name("hostname")
+                .help("The hostname to remove")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let hostname = args
+            .value_of("hostname")
+            .expect("Missing required argument: hostname");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_extra_host_remove(hostname.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get a list of the container's extra host entries")
+            .long_about(concat!(
+                "Get a list of the container's extra host entries. Entries will be printed out, ",
+                "one per line, in the format `hostname:ip`."
+            ))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for host in client
+            .container_extra_host_get_all(container.map(Into::into))
+            .call()?
+            .hosts
+        {
+            writeln!(std::io::stdout(), "{}:{}", host.hostname, host.ip)?;
+        }
+
+        Ok(data)
+    }
+}
@@ -1,3 +1,4 @@
+use anyhow::Context;
 use clap::{App, Arg, ArgMatches};
 
 use std::io::Write;
@@ -19,7 +20,12 @@ impl<'a> CliCommand<'a> for ImageSubcommand {
     }
 
     fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
-        vec![Box::new(GetSubcommand), Box::new(SetSubcommand)]
+        vec![
+            Box::new(GetSubcommand),
+            Box::new(SetSubcommand),
+            Box::new(BuildSubcommand),
+            Box::new(PruneSubcommand),
+        ]
     }
 
     fn get_doc(&self) -> Option<CliDoc> {
@@ -46,6 +52,9 @@ impl<'a> CliCommand<'a> for GetSubcommand {
         self.get_base_app()
             .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
             .about("Get the container image")
+            .arg(Arg::with_name("digest")
+                .help("Get the digest of the image that the container was last run with")
+                .long("digest"))
             .arg(super::container_arg())
     }
 
@@ -73,11 +82,12 @@ impl<'a> CliCommand<'a> for GetSubcommand {
             .call()?;
 
         // Write response
-        writeln!(
-            std::io::stdout(),
-            "{}",
-            response.image.unwrap_or_else(|| "".into())
-        )?;
+        let output = if args.is_present("digest") {
+            response.digest
+        } else {
+            response.image
+        };
+        writeln!(std::io::stdout(), "{}", output.unwrap_or_else(|| "".into()))?;
 
         Ok(data)
     }
@@ -97,9 +107,36 @@ impl<'a> CliCommand<'a> for SetSubcommand {
             .arg(Arg::with_name("image")
                 .help("The container image")
                 .required(true))
+            .arg(Arg::with_name("pull_policy")
+                .help("When to pull the image before running the container")
+                .long_help(concat!(
+                    "When to pull the image before running the container. `if-not-present` will ",
+                    "only pull the image if it doesn't already exist on the host."))
+                .long("pull-policy")
+                .takes_value(true)
+                .possible_values(&["always", "if-not-present", "never"])
+                .default_value("if-not-present"))
             .arg(Arg::with_name("no_pull")
                 .help("Don't attempt to pull image before running container")
+                .long_help("Don't attempt to pull image before running container. Same as \
+                            `--pull-policy never`.")
                 .long("no-pull"))
+            .arg(Arg::with_name("pin")
+                .help("Pull the image now and pin the container to the image's digest")
+                .long_help(concat!(
+                    "Pull the image now and pin the container to the image's digest by rewriting ",
+                    "the image to `repository@sha256:...`. This makes sure that the container ",
+                    "runs exactly the same image even if the image tag is updated."))
+                .long("pin"))
+            .arg(Arg::with_name("registry_auth_from_config")
+                .help("The charm config key containing the registry credentials for the image")
+                .long_help(concat!(
+                    "The charm config key containing the registry credentials used to pull the ",
+                    "image. The config value must be JSON with `username` and `password` or ",
+                    "`identitytoken` fields."))
+                .long("registry-auth-from-config")
+                .takes_value(true)
+                .value_name("key"))
             .arg(super::container_arg())
     }
 
@@ -116,6 +153,12 @@ impl<'a> CliCommand<'a> for SetSubcommand {
             .value_of("image")
             .expect("Missing required argument `image`");
         let container = args.value_of("container");
+        let pull_policy = if args.is_present("no_pull") {
+            "never"
+        } else {
+            args.value_of("pull_policy")
+                .expect("Missing required argument `pull_policy`")
+        };
 
         // Get client connection
         let mut client: Box<VarlinkClient> = data
@@ -129,10 +172,138 @@ impl<'a> CliCommand<'a> for SetSubcommand {
             .container_image_set(
                 image.into(),
                 container.map(Into::into),
-                args.is_present("no_pull"),
+                Some(pull_policy.into()),
+                args.is_present("pin"),
+                args.value_of("registry_auth_from_config").map(Into::into),
             )
             .call()?;
 
         Ok(data)
     }
 }
+
+struct BuildSubcommand;
+
+impl<'a> CliCommand<'a> for BuildSubcommand {
+    fn get_name(&self) -> &'static str {
+        "build"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Build the container image from a Dockerfile in the charm")
+            .long_about(concat!(
+                "Build the container image from the `container_images/<image_name>/Dockerfile` ",
+                "in the charm and use it as the container's image"))
+            .arg(Arg::with_name("image_name")
+                .help("The name of the image dir in the charm's `container_images` dir")
+                .required(true))
+            .arg(Arg::with_name("tag")
+                .help("The tag to give the built image")
+                .long("tag")
+                .short('t')
+                .takes_value(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let image_name = args
+            .value_of("image_name")
+            .expect("Missing required argument `image_name`");
+        let tag = args.value_of("tag");
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Build the image for the specified container
+        let response = client
+            .container_image_build(
+                image_name.into(),
+                tag.map(Into::into),
+                container.map(Into::into),
+            )
+            .call()?;
+
+        // Write out the image ID
+        writeln!(std::io::stdout(), "{}", response.image_id)?;
+
+        Ok(data)
+    }
+}
+
+struct PruneSubcommand;
+
+impl<'a> CliCommand<'a> for PruneSubcommand {
+    fn get_name(&self) -> &'static str {
+        "prune"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .about("Remove images that are no longer used by any of the containers")
+            .long_about(concat!(
+                "Remove the images that the unit's containers have been run with that are no ",
+                "longer used by any of the containers"))
+            .arg(Arg::with_name("keep_last")
+                .help("The number of the most recently used unused images to keep")
+                .long_help(concat!(
+                    "The number of the most recently used unused images to keep so that ",
+                    "containers can be rolled back to them. Defaults to the `keep-last` setting ",
+                    "in the `image-cleanup` section of the `lucky.yaml`."))
+                .long("keep-last")
+                .short('k')
+                .value_name("count")
+                .takes_value(true))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let keep_last = args
+            .value_of("keep_last")
+            .map(|count| count.parse::<u32>())
+            .transpose()
+            .context("Could not parse --keep-last as a number")?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Remove the unused images
+        let response = client
+            .container_image_prune(keep_last.map(Into::into))
+            .call()?;
+
+        // Write out the removed images
+        for image in response.removed {
+            writeln!(std::io::stdout(), "{}", image)?;
+        }
+
+        Ok(data)
+    }
+}
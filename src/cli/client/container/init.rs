@@ -0,0 +1,238 @@
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct InitSubcommand;
+
+impl<'a> CliCommand<'a> for InitSubcommand {
+    fn get_name(&self) -> &'static str {
+        "init"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add and remove init containers that are run before the container is started")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(AddSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_init",
+            content: include_str!("cli_help/init.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct AddSubcommand;
+
+impl<'a> CliCommand<'a> for AddSubcommand {
+    fn get_name(&self) -> &'static str {
+        "add"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Add an init container to the container")
+            .setting(AppSettings::TrailingVarArg)
+            .arg(Arg::with_name("image")
+                .help("The image to run the init container with")
+                .required(true))
+            .arg(Arg::with_name("command")
+                .help("The command to run in the init container. Defaults to the image's command")
+                .multiple(true))
+            .arg(Arg::with_name("entrypoint")
+                .help("The entrypoint of the init container")
+                .long("entrypoint")
+                .short('e')
+                .takes_value(true))
+            .arg(Arg::with_name("env")
+                .help("An environment variable to set in the init container, in the form `key=value`")
+                .long("env")
+                .short('E')
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("volume")
+                .help("A volume to mount in the init container, in the form `source:target`")
+                .long("volume")
+                .short('v')
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let image = args
+            .value_of("image")
+            .expect("Missing required argument: image");
+        let entrypoint = args.value_of("entrypoint");
+        let command: Option<Vec<String>> = args
+            .values_of("command")
+            .map(|values| values.map(Into::into).collect());
+        let volumes: Vec<String> = args
+            .values_of("volume")
+            .map(|values| values.map(Into::into).collect())
+            .unwrap_or_default();
+
+        // Parse the environment variables, dropping variables without values
+        let env = util::parse_kv_pairs(args.values_of("env").into_iter().flatten())?
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_init_add(
+                image.into(),
+                entrypoint.map(Into::into),
+                command,
+                env,
+                volumes,
+                container.map(Into::into),
+            )
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Remove an init container from the container")
+            .arg(Arg::with_name("index")
+                .help("The index of the init container to remove, as shown by `lucky container init list`")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let index: i64 = args
+            .value_of("index")
+            .expect("Missing required argument: index")
+            .parse()
+            .context("Could not parse init container index")?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_init_remove(index, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get a list of the container's init containers")
+            .long_about(concat!(
+                "Get a list of the container's init containers. Init containers will be printed ",
+                "out, one per line, in the order that they are run, in the format ",
+                "`index image [command]`."
+            ))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for (i, init_container) in client
+            .container_init_get_all(container.map(Into::into))
+            .call()?
+            .init_containers
+            .into_iter()
+            .enumerate()
+        {
+            match init_container.command {
+                Some(command) => writeln!(
+                    std::io::stdout(),
+                    "{} {} {}",
+                    i,
+                    init_container.image,
+                    command.join(" ")
+                )?,
+                None => writeln!(std::io::stdout(), "{} {}", i, init_container.image)?,
+            }
+        }
+
+        Ok(data)
+    }
+}
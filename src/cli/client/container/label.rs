@@ -0,0 +1,158 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct LabelSubcommand;
+
+impl<'a> CliCommand<'a> for LabelSubcommand {
+    fn get_name(&self) -> &'static str {
+        "label"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get and set container labels")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![Box::new(GetSubcommand), Box::new(SetSubcommand)]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_label",
+            content: include_str!("cli_help/label.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct GetSubcommand;
+
+impl<'a> CliCommand<'a> for GetSubcommand {
+    fn get_name(&self) -> &'static str {
+        "get"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .about("Get a label")
+            .long_about(concat!(
+                "Get a label of the container. ",
+                "If you leave `key` unspecified, all labels will be printed out, ",
+                "one per line, in the format `key=value`."
+            ))
+            .arg(Arg::with_name("key")
+                .help("The label to get"))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let key = args.value_of("key");
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        let labels = client
+            .container_label_get_all(container.map(Into::into))
+            .call()?
+            .labels;
+
+        // If a specific key was given
+        if let Some(key) = key {
+            // Print out the requested label
+            writeln!(
+                std::io::stdout(),
+                "{}",
+                labels
+                    .into_iter()
+                    .find(|label| label.key == key)
+                    .map_or_else(|| "".into(), |label| label.value)
+            )?;
+
+        // If no key was given
+        } else {
+            // Print out all of the labels
+            for label in labels {
+                writeln!(std::io::stdout(), "{}={}", label.key, label.value)?;
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+struct SetSubcommand;
+
+impl<'a> CliCommand<'a> for SetSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set labels")
+            .arg(Arg::with_name("labels")
+                .help("The labels to set as `key=value` pairs separated by spaces")
+                .long_help("The labels to set as `key=value` pairs separated by spaces. Setting \
+                            values to nothing will remove the label.")
+                .required(true)
+                .multiple(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let raw_labels = args
+            .values_of("labels")
+            .expect("Missing required arg: labels");
+
+        // Parse key-value pairs
+        let labels = util::parse_label_pairs(raw_labels)?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Set the labels. Labels without a value will be removed.
+        client
+            .container_label_set(labels, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,158 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct LimitsSubcommand;
+
+impl<'a> CliCommand<'a> for LimitsSubcommand {
+    fn get_name(&self) -> &'static str {
+        "limits"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get and set container resource limits")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![Box::new(GetSubcommand), Box::new(SetSubcommand)]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_limits",
+            content: include_str!("cli_help/limits.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct GetSubcommand;
+
+impl<'a> CliCommand<'a> for GetSubcommand {
+    fn get_name(&self) -> &'static str {
+        "get"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .about("Get a resource limit")
+            .long_about(concat!(
+                "Get a resource limit of the container. ",
+                "If you leave `name` unspecified, all of the limits that have been set will be ",
+                "printed out, one per line, in the format `name=value`."
+            ))
+            .arg(Arg::with_name("name")
+                .help("The name of the limit to get"))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let name = args.value_of("name");
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        let limits = client
+            .container_limits_get_all(container.map(Into::into))
+            .call()?
+            .limits;
+
+        // If a specific limit was given
+        if let Some(name) = name {
+            // Print out the requested limit
+            writeln!(
+                std::io::stdout(),
+                "{}",
+                limits
+                    .into_iter()
+                    .find(|limit| limit.name == name)
+                    .map_or_else(|| "".into(), |limit| limit.value)
+            )?;
+
+        // If no limit was given
+        } else {
+            // Print out all of the limits
+            for limit in limits {
+                writeln!(std::io::stdout(), "{}={}", limit.name, limit.value)?;
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+struct SetSubcommand;
+
+impl<'a> CliCommand<'a> for SetSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set resource limits")
+            .arg(Arg::with_name("limits")
+                .help("The limits to set as `name=value` pairs separated by spaces")
+                .long_help("The limits to set as `name=value` pairs separated by spaces. Setting \
+                            values to nothing will remove the limit.")
+                .required(true)
+                .multiple(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let raw_limits = args
+            .values_of("limits")
+            .expect("Missing required arg: limits");
+
+        // Parse key-value pairs
+        let limits = util::parse_kv_pairs(raw_limits)?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Set the limits. Limits without a value will be removed.
+        client
+            .container_limits_set(limits, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
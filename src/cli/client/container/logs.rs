@@ -0,0 +1,127 @@
+use anyhow::{format_err, Context};
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct LogsSubcommand;
+
+impl<'a> CliCommand<'a> for LogsSubcommand {
+    fn get_name(&self) -> &'static str {
+        "logs"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .about("Get the logs of a container")
+            .arg(Arg::with_name("follow")
+                .help("Keep printing new logs as they are written")
+                .long("follow")
+                .short('f'))
+            .arg(Arg::with_name("tail")
+                .help("The number of lines to print from the end of the logs")
+                .long("tail")
+                .short('n')
+                .value_name("lines")
+                .takes_value(true))
+            .arg(Arg::with_name("since")
+                .help("Only print logs written since the given time")
+                .long_help(concat!(
+                    "Only print logs written since the given time. The time can be an RFC 3339 ",
+                    "timestamp such as `2020-06-01T15:04:05Z` or a relative time such as `30s`, ",
+                    "`10m`, `2h`, or `1d`."
+                ))
+                .long("since")
+                .value_name("time")
+                .takes_value(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_logs",
+            content: include_str!("cli_help/logs.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let follow = args.is_present("follow");
+        let tail = args
+            .value_of("tail")
+            .map(|tail| tail.parse::<i64>())
+            .transpose()
+            .context("Could not parse --tail as a number")?;
+        let since = args.value_of("since").map(parse_since).transpose()?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Helper to print log lines
+        let print_lines =
+            |lines: Vec<crate::rpc::ContainerLogs_Reply_lines>| -> anyhow::Result<()> {
+                for line in lines {
+                    if line.stderr {
+                        writeln!(std::io::stderr(), "{}", line.text)?;
+                    } else {
+                        writeln!(std::io::stdout(), "{}", line.text)?;
+                    }
+                }
+                Ok(())
+            };
+
+        if follow {
+            // Print the lines as they come in
+            for response in client
+                .container_logs(true, tail, since, container.map(Into::into))
+                .more()?
+            {
+                print_lines(response?.lines)?;
+            }
+        } else {
+            // Print all of the lines
+            print_lines(
+                client
+                    .container_logs(false, tail, since, container.map(Into::into))
+                    .call()?
+                    .lines,
+            )?;
+        }
+
+        Ok(data)
+    }
+}
+
+/// Parse the `--since` argument into a Unix timestamp
+fn parse_since(since: &str) -> anyhow::Result<i64> {
+    // Try to parse an RFC 3339 timestamp
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(since) {
+        return Ok(time.timestamp());
+    }
+
+    // Otherwise parse a relative time
+    let seconds_per_unit = match since.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 60 * 60 * 24,
+        _ => return Err(format_err!("Could not parse --since time: {}", since)),
+    };
+    let number: i64 = since[..since.len() - 1]
+        .parse()
+        .context(format!("Could not parse --since time: {}", since))?;
+
+    Ok(chrono::Utc::now().timestamp() - number * seconds_per_unit)
+}
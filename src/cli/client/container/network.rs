@@ -0,0 +1,198 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct NetworkSubcommand;
+
+impl<'a> CliCommand<'a> for NetworkSubcommand {
+    fn get_name(&self) -> &'static str {
+        "network"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Attach and detach the container from Lucky networks")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(AddSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_network",
+            content: include_str!("cli_help/network.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct AddSubcommand;
+
+impl<'a> CliCommand<'a> for AddSubcommand {
+    fn get_name(&self) -> &'static str {
+        "add"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Attach the container to a network")
+            .arg(Arg::with_name("network_name")
+                .help("The name of the Lucky network")
+                .required(true))
+            .arg(Arg::with_name("alias")
+                .help("An extra name that the container can be reached by on the network")
+                .long("alias")
+                .short('a')
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let network_name = args
+            .value_of("network_name")
+            .expect("Missing required argument: network_name");
+        let aliases = args
+            .values_of("alias")
+            .into_iter()
+            .flatten()
+            .map(Into::into)
+            .collect();
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_network_add(network_name.into(), aliases, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Detach the container from a network")
+            .arg(Arg::with_name("network_name")
+                .help("The name of the Lucky network")
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let network_name = args
+            .value_of("network_name")
+            .expect("Missing required argument: network_name");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_network_remove(network_name.into(), container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Get a list of the networks the container is attached to")
+            .long_about(concat!(
+                "Get a list of the networks the container is attached to. Networks will be ",
+                "printed out, one per line, followed by any aliases, such as ",
+                "`backend alias1 alias2`."
+            ))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for network in client
+            .container_network_get_all(container.map(Into::into))
+            .call()?
+            .networks
+        {
+            let mut line = network.name;
+            for alias in network.aliases {
+                line.push(' ');
+                line.push_str(&alias);
+            }
+            writeln!(std::io::stdout(), "{}", line)?;
+        }
+
+        Ok(data)
+    }
+}
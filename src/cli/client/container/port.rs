@@ -1,6 +1,8 @@
 use anyhow::Context;
 use clap::{App, Arg, ArgMatches};
 
+#[cfg(feature = "daemon")]
+use std::convert::TryFrom;
 use std::io::Write;
 
 use crate::cli::*;
@@ -54,11 +56,25 @@ impl<'a> CliCommand<'a> for AddSubcommand {
             .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
             .about("Add a port binding")
             .arg(Arg::with_name("port_binding")
-                .help("The port binding to add in the format: `host_port:container_port/proto`.")
+                .help("The port binding to add in the format: `[host_ip:]host_port:container_port[/proto]`.")
                 .long_help(concat!(
-                    "The port binding to add in the format: `host_port:container_port/proto`. ",
-                    "the `/proto` suffix is optional and defaults to `/tcp`."
+                    "The port binding to add in the format: ",
+                    "`[host_ip:]host_port:container_port[/proto]`. The `/proto` suffix is ",
+                    "optional and defaults to `/tcp`. The host IP is optional and IPv6 addresses ",
+                    "must be in brackets, such as `[::1]`. Port ranges can be bound by giving ",
+                    "ranges of the same size for the host and container ports, such as ",
+                    "`30000-30100:30000-30100/udp`. If the host port is `0` or `auto`, a free ",
+                    "host port will be allocated when the container is deployed."
                 )))
+            .arg(Arg::with_name("expose")
+                .help("Open the host port through Juju")
+                .long_help(concat!(
+                    "Open the host port through Juju so that it is reachable when the ",
+                    "application is exposed with `juju expose`. The port will be closed when the ",
+                    "port binding is removed."
+                ))
+                .long("expose")
+                .short('e'))
             .arg(super::container_arg())
     }
 
@@ -78,6 +94,7 @@ impl<'a> CliCommand<'a> for AddSubcommand {
     #[cfg(feature = "daemon")]
     fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
         let container = args.value_of("container");
+        let expose = args.is_present("expose");
 
         // Get client connection
         let mut client: Box<VarlinkClient> = data
@@ -94,12 +111,7 @@ impl<'a> CliCommand<'a> for AddSubcommand {
             .context("Could not parse port binding")?;
 
         client
-            .container_port_add(
-                port_binding.host_port.into(),
-                port_binding.container_port.into(),
-                port_binding.protocol,
-                container.map(Into::into),
-            )
+            .container_port_add(port_binding.into(), expose, container.map(Into::into))
             .call()?;
 
         Ok(data)
@@ -118,10 +130,11 @@ impl<'a> CliCommand<'a> for RemoveSubcommand {
         self.get_base_app()
             .about("Remove a port binding")
             .arg(Arg::with_name("port_binding")
-                .help("The port binding to remove in the format: `host_port:container_port/proto`.")
+                .help("The port binding to remove in the format: `[host_ip:]host_port:container_port[/proto]`.")
                 .long_help(concat!(
-                    "The port binding to remove in the format: `host_port:container_port/proto`. ",
-                    "the `/proto` suffix is optional and defaults to `/tcp`."
+                    "The port binding to remove in the format: ",
+                    "`[host_ip:]host_port:container_port[/proto]`. The `/proto` suffix is ",
+                    "optional and defaults to `/tcp`."
                 ))
                 .required_unless("all"))
             .arg(Arg::with_name("all")
@@ -169,12 +182,7 @@ impl<'a> CliCommand<'a> for RemoveSubcommand {
                 .context("Could not parse port binding")?;
 
             client
-                .container_port_remove(
-                    port_binding.host_port.into(),
-                    port_binding.container_port.into(),
-                    port_binding.protocol,
-                    container.map(Into::into),
-                )
+                .container_port_remove(port_binding.into(), container.map(Into::into))
                 .call()?;
         }
 
@@ -227,10 +235,8 @@ impl<'a> CliCommand<'a> for ListSubcommand {
         {
             writeln!(
                 std::io::stdout(),
-                "{}:{}/{}",
-                port_binding.host_port,
-                port_binding.container_port,
-                port_binding.protocol
+                "{}",
+                PortBinding::try_from(port_binding)?
             )?;
         }
 
@@ -0,0 +1,161 @@
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Read;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct RegistrySubcommand;
+
+impl<'a> CliCommand<'a> for RegistrySubcommand {
+    fn get_name(&self) -> &'static str {
+        "registry"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Log in to and out of container registries")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![Box::new(LoginSubcommand), Box::new(LogoutSubcommand)]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_registry",
+            content: include_str!("cli_help/registry.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct LoginSubcommand;
+
+impl<'a> CliCommand<'a> for LoginSubcommand {
+    fn get_name(&self) -> &'static str {
+        "login"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Log in to a container registry")
+            .long_about(concat!(
+                "Log in to a container registry. The password or identity token is read from ",
+                "stdin so that it doesn't show up in the process list."))
+            .arg(Arg::with_name("registry")
+                .help("The registry to log in to, such as `docker.io` or `quay.io`")
+                .required(true))
+            .arg(Arg::with_name("username")
+                .help("The username to log in with")
+                .long("username")
+                .short('u')
+                .takes_value(true)
+                .required_unless("identity_token_stdin"))
+            .arg(Arg::with_name("password_stdin")
+                .help("Read the password from stdin")
+                .long("password-stdin")
+                .requires("username")
+                .conflicts_with("identity_token_stdin"))
+            .arg(Arg::with_name("identity_token_stdin")
+                .help("Read an identity token from stdin instead of using a username and password")
+                .long("identity-token-stdin"))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let registry = args
+            .value_of("registry")
+            .expect("Missing required argument `registry`");
+        let username = args.value_of("username");
+
+        // Read the secret from stdin
+        let mut secret = String::new();
+        if args.is_present("password_stdin") || args.is_present("identity_token_stdin") {
+            std::io::stdin()
+                .read_to_string(&mut secret)
+                .context("Could not read secret from stdin")?;
+        }
+        let secret = secret.trim_end_matches(&['\r', '\n'][..]).to_string();
+        let (password, identity_token) = if args.is_present("identity_token_stdin") {
+            (None, Some(secret))
+        } else {
+            (Some(secret), None)
+        };
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Log in to the registry
+        client
+            .container_registry_login(
+                registry.into(),
+                username.map(Into::into),
+                password,
+                identity_token,
+            )
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct LogoutSubcommand;
+
+impl<'a> CliCommand<'a> for LogoutSubcommand {
+    fn get_name(&self) -> &'static str {
+        "logout"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Log out of a container registry")
+            .arg(Arg::with_name("registry")
+                .help("The registry to log out of")
+                .required(true))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let registry = args
+            .value_of("registry")
+            .expect("Missing required argument `registry`");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Log out of the registry
+        client.container_registry_logout(registry.into()).call()?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,53 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetAutoExposeSubcommand;
+
+impl<'a> CliCommand<'a> for SetAutoExposeSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-auto-expose"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set whether or not all of the container's host ports are opened through Juju")
+            .arg(Arg::with_name("auto_expose")
+                .help("Whether or not to open all of the container's host ports")
+                .possible_values(&["true", "false"])
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-auto-expose",
+            content: include_str!("cli_help/set_auto_expose.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let auto_expose = args.value_of("auto_expose") == Some("true");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Set auto-expose
+        client
+            .container_set_auto_expose(auto_expose, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
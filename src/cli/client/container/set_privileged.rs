@@ -0,0 +1,53 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetPrivilegedSubcommand;
+
+impl<'a> CliCommand<'a> for SetPrivilegedSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-privileged"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set whether or not the container runs in privileged mode")
+            .arg(Arg::with_name("privileged")
+                .help("Whether or not the container should be privileged")
+                .possible_values(&["true", "false"])
+                .required(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-privileged",
+            content: include_str!("cli_help/set_privileged.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let privileged = args.value_of("privileged") == Some("true");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Set privileged mode
+        client
+            .container_set_privileged(privileged, container.map(Into::into))
+            .call()?;
+
+        Ok(data)
+    }
+}
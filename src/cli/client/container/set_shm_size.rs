@@ -0,0 +1,69 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetShmSizeSubcommand;
+
+impl<'a> CliCommand<'a> for SetShmSizeSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-shm-size"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set the size of the container's /dev/shm")
+            .arg(Arg::with_name("size")
+                .help("The size of /dev/shm such as `64m` or `1g`")
+                .required_unless("unset"))
+            .arg(Arg::with_name("unset")
+                .help("Unset the shm size instead of setting it")
+                .long_help("Unset the shm size instead of setting it. The container will use \
+                            Docker's default shm size.")
+                .long("unset")
+                .short('u')
+                .required_unless("size"))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-shm-size",
+            content: include_str!("cli_help/set_shm_size.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let size = args.value_of("size");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        if args.is_present("unset") {
+            // Unset the shm size
+            client
+                .container_set_shm_size(None, container.map(Into::into))
+                .call()?;
+        } else {
+            // Set the shm size
+            client
+                .container_set_shm_size(
+                    Some(size.expect("Missing required argument: size").into()),
+                    container.map(Into::into),
+                )
+                .call()?;
+        }
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,98 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetUpdateStrategySubcommand;
+
+impl<'a> CliCommand<'a> for SetUpdateStrategySubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-update-strategy"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set how the container is replaced when it is updated")
+            .arg(Arg::with_name("strategy")
+                .help("The update strategy")
+                .long_help(concat!(
+                    "The update strategy. `stop-first` will stop the old container before ",
+                    "starting the new one. `start-first` will start the new container before ",
+                    "removing the old one."))
+                .possible_values(&["stop-first", "start-first"])
+                .required(true))
+            .arg(Arg::with_name("health_check_timeout")
+                .help("How many seconds to wait for the new container's health check to pass")
+                .long_help(concat!(
+                    "How many seconds to wait for the new container's health check to pass ",
+                    "before removing the old container. If the image doesn't have a health ",
+                    "check, the container only has to be running."))
+                .long("health-check-timeout")
+                .short('t')
+                .takes_value(true))
+            .arg(Arg::with_name("startup_grace_period")
+                .help("How many seconds the new container must keep running after it is started")
+                .long_help(concat!(
+                    "How many seconds the new container must keep running after it is started ",
+                    "for the update to be considered successful, if a health check timeout is ",
+                    "not set. Defaults to 3 seconds. Set to 0 to only check that the container ",
+                    "was started."))
+                .long("startup-grace-period")
+                .short('g')
+                .takes_value(true))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-update-strategy",
+            content: include_str!("cli_help/set_update_strategy.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let strategy = args
+            .value_of("strategy")
+            .expect("Missing required argument: strategy");
+        let health_check_timeout = args
+            .value_of("health_check_timeout")
+            .map(|timeout| {
+                timeout
+                    .parse::<i64>()
+                    .map_err(|_| format_err!("Invalid health check timeout: {}", timeout))
+            })
+            .transpose()?;
+        let startup_grace_period = args
+            .value_of("startup_grace_period")
+            .map(|period| {
+                period
+                    .parse::<i64>()
+                    .map_err(|_| format_err!("Invalid startup grace period: {}", period))
+            })
+            .transpose()?;
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .container_set_update_strategy(
+                strategy.into(),
+                health_check_timeout,
+                startup_grace_period,
+                container.map(Into::into),
+            )
+            .call()?;
+
+        Ok(data)
+    }
+}
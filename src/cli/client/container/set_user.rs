@@ -0,0 +1,69 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetUserSubcommand;
+
+impl<'a> CliCommand<'a> for SetUserSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-user"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set the user that the container runs as")
+            .arg(Arg::with_name("user")
+                .help("The user to run the container as in the format `user[:group]`")
+                .required_unless("unset"))
+            .arg(Arg::with_name("unset")
+                .help("Unset the user instead of setting it")
+                .long_help("Unset the user instead of setting it. The container will use the \
+                            default user.")
+                .long("unset")
+                .short('u')
+                .required_unless("user"))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-user",
+            content: include_str!("cli_help/set_user.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let user = args.value_of("user");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        if args.is_present("unset") {
+            // Unset the user
+            client
+                .container_set_user(None, container.map(Into::into))
+                .call()?;
+        } else {
+            // Set the user
+            client
+                .container_set_user(
+                    Some(user.expect("Missing required argument: user").into()),
+                    container.map(Into::into),
+                )
+                .call()?;
+        }
+
+        Ok(data)
+    }
+}
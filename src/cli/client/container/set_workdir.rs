@@ -0,0 +1,69 @@
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct SetWorkdirSubcommand;
+
+impl<'a> CliCommand<'a> for SetWorkdirSubcommand {
+    fn get_name(&self) -> &'static str {
+        "set-workdir"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Set the container working directory")
+            .arg(Arg::with_name("workdir")
+                .help("The working directory for commands run in the container")
+                .required_unless("unset"))
+            .arg(Arg::with_name("unset")
+                .help("Unset the working directory instead of setting it")
+                .long_help("Unset the working directory instead of setting it. The container will \
+                            use the default working directory.")
+                .long("unset")
+                .short('u')
+                .required_unless("workdir"))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_set-workdir",
+            content: include_str!("cli_help/set_workdir.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let workdir = args.value_of("workdir");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        if args.is_present("unset") {
+            // Unset the working directory
+            client
+                .container_set_working_dir(None, container.map(Into::into))
+                .call()?;
+        } else {
+            // Set the working directory
+            client
+                .container_set_working_dir(
+                    Some(workdir.expect("Missing required argument: workdir").into()),
+                    container.map(Into::into),
+                )
+                .call()?;
+        }
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,127 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct StatsSubcommand;
+
+impl<'a> CliCommand<'a> for StatsSubcommand {
+    fn get_name(&self) -> &'static str {
+        "stats"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .about("Get the resource usage of a container")
+            .arg(Arg::with_name("all")
+                .help("Get the resource usage of all of the containers")
+                .long("all")
+                .short('A')
+                .conflicts_with("container"))
+            .arg(Arg::with_name("json")
+                .help("Print the stats as JSON")
+                .long("json")
+                .short('j'))
+            .arg(super::container_arg())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_container_stats",
+            content: include_str!("cli_help/stats.md"),
+        })
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let container = args.value_of("container");
+        let all = args.is_present("all");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        // Get the stats
+        let stats = client
+            .container_stats(all, container.map(Into::into))
+            .call()?
+            .stats;
+
+        if args.is_present("json") {
+            writeln!(
+                std::io::stdout(),
+                "{}",
+                serde_json::to_string_pretty(&stats)?
+            )?;
+        } else {
+            writeln!(
+                std::io::stdout(),
+                "{:<20} {:>8} {:>21} {:>21} {:>21} {:>8}",
+                "CONTAINER",
+                "CPU %",
+                "MEM USAGE / LIMIT",
+                "NET I/O",
+                "BLOCK I/O",
+                "RESTARTS"
+            )?;
+            for stat in stats {
+                writeln!(
+                    std::io::stdout(),
+                    "{:<20} {:>8} {:>21} {:>21} {:>21} {:>8}",
+                    stat.container_name.as_deref().unwrap_or("default"),
+                    if stat.running {
+                        format!("{:.2}%", stat.cpu_percent)
+                    } else {
+                        "stopped".into()
+                    },
+                    format!(
+                        "{} / {}",
+                        format_bytes(stat.memory_usage),
+                        format_bytes(stat.memory_limit)
+                    ),
+                    format!(
+                        "{} / {}",
+                        format_bytes(stat.network_rx),
+                        format_bytes(stat.network_tx)
+                    ),
+                    format!(
+                        "{} / {}",
+                        format_bytes(stat.block_read),
+                        format_bytes(stat.block_write)
+                    ),
+                    stat.restart_count
+                )?;
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Format a number of bytes as a human readable size, such as `12.3MiB`
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1}{}", size, UNITS[unit])
+    }
+}
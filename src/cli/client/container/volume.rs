@@ -1,9 +1,10 @@
 use clap::{App, AppSettings, Arg, ArgMatches};
 
+use std::collections::HashMap;
 use std::io::Write;
 
 use crate::cli::*;
-use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+use crate::rpc::{MountOptions, VarlinkClient, VarlinkClientInterface};
 
 pub(super) struct VolumeSubcommand;
 
@@ -58,11 +59,43 @@ impl<'a> CliCommand<'a> for AddSubcommand {
             .arg(Arg::with_name("source")
                 .help("The source for the volume.")
                 .long_help("The source for the volume: either a name for a named volume or an \
-                            absolute path on the host"
+                            absolute path on the host. For tmpfs mounts the source can be \
+                            omitted."
                 ))
             .arg(Arg::with_name("target")
                 .help("The absolute path in the container to mount `source` to")
                 .value_name("container_path"))
+            .arg(Arg::with_name("type")
+                .help("The type of mount")
+                .long_help("The type of mount: `bind` for host paths and Lucky named volumes, \
+                            `volume` for Docker named volumes, or `tmpfs` for tmpfs mounts")
+                .long("type")
+                .short('t')
+                .takes_value(true)
+                .possible_values(&["bind", "volume", "tmpfs"]))
+            .arg(Arg::with_name("read_only")
+                .help("Mount the volume read-only")
+                .long("read-only")
+                .visible_alias("ro"))
+            .arg(Arg::with_name("propagation")
+                .help("The bind propagation mode for bind mounts")
+                .long("propagation")
+                .takes_value(true)
+                .possible_values(&["private", "rprivate", "shared", "rshared", "slave", "rslave"]))
+            .arg(Arg::with_name("driver")
+                .help("The volume driver for Docker named volumes")
+                .long("driver")
+                .takes_value(true))
+            .arg(Arg::with_name("driver_opt")
+                .help("A `key=value` option for the volume driver. May be repeated.")
+                .long("driver-opt")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1))
+            .arg(Arg::with_name("tmpfs_size")
+                .help("The size limit for tmpfs mounts, such as `64m`")
+                .long("tmpfs-size")
+                .takes_value(true))
             .arg(super::container_arg())
     }
 
@@ -75,14 +108,45 @@ impl<'a> CliCommand<'a> for AddSubcommand {
     }
 
     fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
-        let source = args
-            .value_of("source")
-            .expect("Missing required argument: source");
-        let target = args
-            .value_of("target")
-            .expect("Missing required argument: target");
+        let mount_type = args.value_of("type");
+        // Tmpfs mounts don't need a source so a single path is taken as the target
+        let (source, target) = match (args.value_of("source"), args.value_of("target")) {
+            (Some(target), None) if mount_type == Some("tmpfs") => ("tmpfs", target),
+            (Some(source), Some(target)) => (source, target),
+            _ => anyhow::bail!("Missing required argument: target"),
+        };
         let container = args.value_of("container");
 
+        // Collect the mount options if any have been specified
+        let has_options = [
+            "type",
+            "read_only",
+            "propagation",
+            "driver",
+            "driver_opt",
+            "tmpfs_size",
+        ]
+        .iter()
+        .any(|&arg| args.is_present(arg));
+        let options = if has_options {
+            let raw_driver_options = args.values_of("driver_opt").into_iter().flatten();
+            let driver_options: HashMap<String, String> = util::parse_kv_pairs(raw_driver_options)?
+                .into_iter()
+                .map(|(key, value)| (key, value.unwrap_or_default()))
+                .collect();
+
+            Some(MountOptions {
+                mount_type: mount_type.map(Into::into),
+                read_only: args.is_present("read_only"),
+                propagation: args.value_of("propagation").map(Into::into),
+                driver: args.value_of("driver").map(Into::into),
+                driver_options,
+                tmpfs_size: args.value_of("tmpfs_size").map(Into::into),
+            })
+        } else {
+            None
+        };
+
         // Get client connection
         let mut client: Box<VarlinkClient> = data
             .remove("client")
@@ -91,7 +155,12 @@ impl<'a> CliCommand<'a> for AddSubcommand {
             .expect("Invalid type");
 
         client
-            .container_volume_add(source.into(), target.into(), container.map(Into::into))
+            .container_volume_add(
+                source.into(),
+                target.into(),
+                container.map(Into::into),
+                options,
+            )
             .call()?;
 
         Ok(data)
@@ -213,7 +282,22 @@ impl<'a> CliCommand<'a> for GetSubcommand {
         } else {
             // Print all of the volumes
             for volume in volumes {
-                writeln!(std::io::stdout(), "{}:{}", volume.source, volume.target)?;
+                // Add the mount type and read-only flag for mounts with options
+                let suffix = match volume.options {
+                    Some(options) => format!(
+                        " ({}{})",
+                        options.mount_type.unwrap_or_else(|| "bind".into()),
+                        if options.read_only { ", ro" } else { "" }
+                    ),
+                    None => "".into(),
+                };
+                writeln!(
+                    std::io::stdout(),
+                    "{}:{}{}",
+                    volume.source,
+                    volume.target,
+                    suffix
+                )?;
             }
         }
 
@@ -0,0 +1,187 @@
+use clap::{App, Arg, ArgMatches};
+
+use std::io::Write;
+
+use crate::cli::*;
+use crate::rpc::{VarlinkClient, VarlinkClientInterface};
+
+pub(super) struct NetworkSubcommand;
+
+impl<'a> CliCommand<'a> for NetworkSubcommand {
+    fn get_name(&self) -> &'static str {
+        "network"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .about("Create and remove Docker networks for the charm's containers")
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![
+            Box::new(CreateSubcommand),
+            Box::new(RemoveSubcommand),
+            Box::new(ListSubcommand),
+        ]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        Some(CliDoc {
+            name: "lucky_client_network",
+            content: include_str!("cli_help/network.md"),
+        })
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        Ok(data)
+    }
+}
+
+struct CreateSubcommand;
+
+impl<'a> CliCommand<'a> for CreateSubcommand {
+    fn get_name(&self) -> &'static str {
+        "create"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Create a network")
+            .long_about(concat!(
+                "Create a network. Creating a network that already exists will not do anything."))
+            .arg(Arg::with_name("network_name")
+                .help("The name of the network")
+                .required(true))
+            .arg(Arg::with_name("internal")
+                .help("Restrict external access to the network")
+                .long("internal"))
+            .arg(Arg::with_name("subnet")
+                .help("The subnet of the network in CIDR format, such as `172.28.0.0/16`")
+                .long("subnet")
+                .takes_value(true))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let network_name = args
+            .value_of("network_name")
+            .expect("Missing required argument: network_name");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client
+            .network_create(
+                network_name.into(),
+                args.is_present("internal"),
+                args.value_of("subnet").map(Into::into),
+            )
+            .call()?;
+
+        Ok(data)
+    }
+}
+
+struct RemoveSubcommand;
+
+impl<'a> CliCommand<'a> for RemoveSubcommand {
+    fn get_name(&self) -> &'static str {
+        "remove"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Remove a network")
+            .long_about(concat!(
+                "Remove a network. The network must not be used by any containers."))
+            .arg(Arg::with_name("network_name")
+                .help("The name of the network")
+                .required(true))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        let network_name = args
+            .value_of("network_name")
+            .expect("Missing required argument: network_name");
+
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        client.network_remove(network_name.into()).call()?;
+
+        Ok(data)
+    }
+}
+
+struct ListSubcommand;
+
+impl<'a> CliCommand<'a> for ListSubcommand {
+    fn get_name(&self) -> &'static str {
+        "list"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("List the charm's networks")
+            .long_about(concat!(
+                "List the charm's networks. Networks will be printed out, one per line, in the ",
+                "format `name:docker_name`."
+            ))
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, _args: &ArgMatches, mut data: CliData) -> anyhow::Result<CliData> {
+        // Get client connection
+        let mut client: Box<VarlinkClient> = data
+            .remove("client")
+            .expect("Missing client data")
+            .downcast()
+            .expect("Invalid type");
+
+        for network in client.network_list().call()?.networks {
+            writeln!(
+                std::io::stdout(),
+                "{}:{}",
+                network.name,
+                network.docker_name
+            )?;
+        }
+
+        Ok(data)
+    }
+}
@@ -3,8 +3,9 @@ use clap::{App, Arg, ArgMatches};
 
 use std::sync::{Arc, RwLock};
 
+mod container_died;
+mod container_started;
 mod cron_tick;
-mod exit_code_helper;
 mod start;
 mod stop;
 mod trigger_hook;
@@ -28,8 +29,9 @@ impl<'a> CliCommand<'a> for DaemonSubcommand {
             Box::new(start::StartSubcommand),
             Box::new(stop::StopSubcommand),
             Box::new(trigger_hook::TriggerHookSubcommand),
-            Box::new(exit_code_helper::ExitCodeHelperSubcommand),
             Box::new(cron_tick::CronTickSubcommand),
+            Box::new(container_died::ContainerDiedSubcommand),
+            Box::new(container_started::ContainerStartedSubcommand),
         ]
     }
 
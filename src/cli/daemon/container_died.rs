@@ -0,0 +1,73 @@
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::daemon::{get_daemon_client, get_daemon_connection_args, get_daemon_socket_path};
+use crate::cli::*;
+use crate::rpc::VarlinkClientInterface;
+
+pub(super) struct ContainerDiedSubcommand;
+
+impl<'a> CliCommand<'a> for ContainerDiedSubcommand {
+    fn get_name(&self) -> &'static str {
+        "container-died"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Notify the daemon that one of its containers has exited")
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .arg(Arg::with_name("container")
+                .long("container")
+                .short('c')
+                .takes_value(true)
+                .help("The name of the container that exited. Omit for the default container"))
+            .arg(Arg::with_name("exit_code")
+                .long("exit-code")
+                .takes_value(true)
+                .required(true)
+                .help("The exit code of the container"))
+            .arg(Arg::with_name("oom_killed")
+                .long("oom-killed")
+                .help("Indicates that the container was killed for running out of memory"))
+            .args(&get_daemon_connection_args())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        let socket_path = get_daemon_socket_path(args);
+
+        let juju_context_id = std::env::var("JUJU_CONTEXT_ID").context(concat!(
+            "JUJU_CONTEXT_ID environment var must be present. Maybe you need to run this ",
+            "command using `juju-run`?"
+        ))?;
+        let container_name = args.value_of("container").map(ToOwned::to_owned);
+        let exit_code: i64 = args
+            .value_of("exit_code")
+            .expect("Missing required argument: exit_code")
+            .parse()
+            .context("Could not parse exit code as an integer")?;
+        let oom_killed = args.is_present("oom_killed");
+
+        // Connect to lucky daemon
+        let mut client = get_daemon_client(&socket_path)?;
+
+        log::info!("Notifying daemon of container exit");
+
+        // Notify the daemon and exit
+        client
+            .container_died(juju_context_id, container_name, exit_code, oom_killed)
+            .call()?;
+
+        log::info!("Done handling container exit");
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Context;
+use clap::{App, Arg, ArgMatches};
+
+use crate::cli::daemon::{get_daemon_client, get_daemon_connection_args, get_daemon_socket_path};
+use crate::cli::*;
+use crate::rpc::VarlinkClientInterface;
+
+pub(super) struct ContainerStartedSubcommand;
+
+impl<'a> CliCommand<'a> for ContainerStartedSubcommand {
+    fn get_name(&self) -> &'static str {
+        "container-started"
+    }
+
+    #[rustfmt::skip]
+    fn get_app(&self) -> App<'a> {
+        self.get_base_app()
+            .about("Notify the daemon that one of its containers that exited has been started again")
+            .unset_setting(clap::AppSettings::ArgRequiredElseHelp)
+            .arg(Arg::with_name("container")
+                .long("container")
+                .short('c')
+                .takes_value(true)
+                .help("The name of the container that was started. Omit for the default container"))
+            .args(&get_daemon_connection_args())
+    }
+
+    fn get_subcommands(&self) -> Vec<Box<dyn CliCommand<'a>>> {
+        vec![]
+    }
+
+    fn get_doc(&self) -> Option<CliDoc> {
+        None
+    }
+
+    fn execute_command(&self, args: &ArgMatches, data: CliData) -> anyhow::Result<CliData> {
+        let socket_path = get_daemon_socket_path(args);
+
+        let juju_context_id = std::env::var("JUJU_CONTEXT_ID").context(concat!(
+            "JUJU_CONTEXT_ID environment var must be present. Maybe you need to run this ",
+            "command using `juju-run`?"
+        ))?;
+        let container_name = args.value_of("container").map(ToOwned::to_owned);
+
+        // Connect to lucky daemon
+        let mut client = get_daemon_client(&socket_path)?;
+
+        log::info!("Notifying daemon of container start");
+
+        // Notify the daemon and exit
+        client
+            .container_started(juju_context_id, container_name)
+            .call()?;
+
+        log::info!("Done handling container start");
+
+        Ok(data)
+    }
+}
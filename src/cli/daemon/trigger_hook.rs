@@ -4,6 +4,7 @@ use std::collections::HashMap;
 
 use crate::cli::daemon::{get_daemon_client, get_daemon_connection_args, get_daemon_socket_path};
 use crate::cli::*;
+use crate::docker::ProxySettings;
 use crate::rpc::VarlinkClientInterface;
 
 pub(super) struct TriggerHookSubcommand;
@@ -38,13 +39,16 @@ impl<'a> CliCommand<'a> for TriggerHookSubcommand {
 
         // Populate environment variables the Lucky daemon may need for executing the hook
         let mut environment: HashMap<String, String> = HashMap::new();
-        for &var in &[
+        for &var in [
             "JUJU_RELATION",
             "JUJU_RELATION_ID",
             "JUJU_REMOTE_UNIT",
             "JUJU_CONTEXT_ID",
             "JUJU_REMOTE_APP",
-        ] {
+        ]
+        .iter()
+        .chain(ProxySettings::ENV_VARS)
+        {
             if let Ok(value) = std::env::var(var) {
                 environment.insert(var.into(), value);
             }
@@ -10,18 +10,43 @@ lazy_static! {
     /// The regular expression for a key-value pair
     pub(crate) static ref KV_REGEX: Regex = Regex::new(r"(?ms)^(?P<key>[a-zA-Z][a-zA-Z0-9_-]*)=(?P<value>.*)")
         .expect("Could not compile regex");
+    /// The regular expression for a container label key-value pair. Label keys may also contain
+    /// dots, such as `com.example.version`.
+    pub(crate) static ref LABEL_KV_REGEX: Regex = Regex::new(r"(?ms)^(?P<key>[a-zA-Z][a-zA-Z0-9_.-]*)=(?P<value>.*)")
+        .expect("Could not compile regex");
 }
 
 pub(crate) fn parse_kv_pairs<'a, T>(
     raw_kv_pairs: T,
 ) -> anyhow::Result<HashMap<String, Option<String>>>
+where
+    T: IntoIterator<Item = &'a str>,
+{
+    parse_pairs(&KV_REGEX, raw_kv_pairs)
+}
+
+/// Parse container label key-value pairs, which may have dots in their keys
+pub(crate) fn parse_label_pairs<'a, T>(
+    raw_label_pairs: T,
+) -> anyhow::Result<HashMap<String, Option<String>>>
+where
+    T: IntoIterator<Item = &'a str>,
+{
+    parse_pairs(&LABEL_KV_REGEX, raw_label_pairs)
+}
+
+/// Parse key-value pairs with a regex that has `key` and `value` captures
+fn parse_pairs<'a, T>(
+    regex: &Regex,
+    raw_kv_pairs: T,
+) -> anyhow::Result<HashMap<String, Option<String>>>
 where
     T: IntoIterator<Item = &'a str>,
 {
     let mut data = HashMap::new();
 
     for raw_kv_pair in raw_kv_pairs {
-        if let Some(captures) = KV_REGEX.captures(raw_kv_pair) {
+        if let Some(captures) = regex.captures(raw_kv_pair) {
             let key = captures.name("key").expect("Expected key").as_str();
 
             let value = captures.name("value").expect("Expected value").as_str();
@@ -3,24 +3,30 @@ use anyhow::Context;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
-use shiplift::Docker;
 
 use crossbeam::{channel::unbounded as unbounded_channel, scope as thread_scope};
 
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc, Mutex, RwLock,
 };
 
-use crate::docker::{ContainerInfo, PortBinding, VolumeSource, VolumeTarget};
+use crate::docker::runtime::{ContainerLogLine, ContainerLogOptions, ContainerRuntime};
+use crate::docker::{
+    ContainerInfo, MountOptions, MountType, PortBinding, ProxySettings, PullPolicy,
+    RegistryCredentials, TrackedImage, UpdateStrategy, VolumeSource, VolumeTarget,
+    DEFAULT_CONTAINER_NAME, LUCKY_NETWORK_LABEL, LUCKY_UNIT_LABEL,
+};
 use crate::juju;
 use crate::rpc;
-use crate::types::{LuckyMetadata, ScriptStatus};
+use crate::types::{
+    ContainerDefinition, JobContainerDefinition, LuckyMetadata, ScriptState, ScriptStatus,
+};
 
 use crate::VOLUME_DIR;
 
@@ -31,6 +37,12 @@ enum Void {}
 mod tools;
 // Built-in daemon hook handlers
 mod hook_handlers;
+// Docker event watcher
+mod events;
+// Containers declared in the lucky.yaml
+mod container_definitions;
+// Docker settings declared in the lucky.yaml
+mod docker_settings;
 // Daemon helper types
 mod types;
 use types::*;
@@ -51,6 +63,28 @@ struct DaemonState {
     named_containers: HashMap<String, Cd<ContainerInfo>>,
     /// The cached charm config obtained from Juju's `config-get` hook tool
     charm_config: HashMap<String, Cd<JsonValue>>,
+    /// Container registry credentials by registry. These are kept in memory only so that secrets
+    /// are never written to the state file.
+    #[serde(skip)]
+    registry_credentials: HashMap<String, RegistryCredentials>,
+    /// The Lucky networks that have been created, mapped to their Docker network IDs
+    #[serde(default)]
+    networks: HashMap<String, String>,
+    /// The rendered container definitions from the `lucky.yaml` that were last applied
+    #[serde(default)]
+    declared_containers: HashMap<String, ContainerDefinition>,
+    /// The Juju model's proxy settings from the last hook environment
+    #[serde(default)]
+    proxy_settings: ProxySettings,
+    /// The ports, such as `8080/tcp`, that Lucky opened through Juju for exposed container ports
+    #[serde(default)]
+    exposed_ports: HashSet<String>,
+    /// The images that the unit's containers have been run with, from least to most recently used
+    #[serde(default)]
+    images: Vec<TrackedImage>,
+    /// The keys in Docker's `daemon.json` that were set from the `lucky.yaml` Docker settings
+    #[serde(default)]
+    docker_daemon_config_keys: HashSet<String>,
 }
 
 /// The Lucky Daemon RPC service
@@ -71,8 +105,13 @@ struct LuckyDaemon {
     state: Arc<RwLock<DaemonState>>,
     /// The last time that the cron tick was run
     last_cron_tick: Arc<Mutex<DateTime<Local>>>,
-    /// The docker daemon connection if it has been loaded
-    docker_conn: Arc<Mutex<Option<Arc<Mutex<Docker>>>>>,
+    /// The container runtime that the charm's containers are run with
+    runtime: Arc<dyn ContainerRuntime>,
+    /// Held while container updates are being applied so that only one set of updates is applied
+    /// at a time, even while the state lock is released
+    container_updates_lock: Arc<Mutex<()>>,
+    /// Used to ignore container events while the container runtime is being restarted
+    event_suppression: Arc<EventSuppression>,
 }
 
 pub(crate) struct LuckyDaemonOptions {
@@ -98,12 +137,31 @@ macro_rules! handle_err {
     };
 }
 
+/// Get a container by its name, or the default container if the name is `None`
+fn get_container_mut<'a>(
+    state: &'a mut DaemonState,
+    container_name: Option<&str>,
+) -> Option<&'a mut Cd<ContainerInfo>> {
+    match container_name {
+        Some(container_name) => state.named_containers.get_mut(container_name),
+        None => state.default_container.as_mut(),
+    }
+}
+
+/// Get the suffix that identifies a named container in log messages, such as `[database]`
+///
+/// The suffix is empty for the default container.
+fn container_log_suffix(container_name: Option<&str>) -> String {
+    container_name.map_or("".into(), |name| format!("[{}]", name))
+}
+
 impl LuckyDaemon {
     /// Create a new daemon instance
     ///
     /// `stop_listening` will be set to `true` by the daemon if it recieves a `StopDaemon` RPC. The
     /// actual stopping of the server itself is not handled by the daemon.
     fn new(options: LuckyDaemonOptions) -> Self {
+        let runtime = crate::docker::runtime::get_runtime(&options.lucky_metadata);
         let daemon = LuckyDaemon {
             lucky_metadata: options.lucky_metadata,
             charm_dir: options.charm_dir,
@@ -112,7 +170,9 @@ impl LuckyDaemon {
             stop_listening: options.stop_listening,
             state: Default::default(),
             last_cron_tick: Arc::new(Mutex::new(Local::now())),
-            docker_conn: Arc::new(Mutex::new(None)),
+            runtime,
+            container_updates_lock: Arc::new(Mutex::new(())),
+            event_suppression: Default::default(),
         };
 
         // Load daemon state
@@ -120,6 +180,18 @@ impl LuckyDaemon {
             .context("Could not load daemon state from filesystem")
             .unwrap_or_else(|e| log::error!("{:?}", e));
 
+        // Reconcile the loaded state with the containers that actually exist in Docker
+        if daemon.lucky_metadata.use_docker {
+            if let Err(e) =
+                tools::reconcile_containers(&*daemon.runtime, &mut daemon.state.write().unwrap())
+            {
+                log::warn!(
+                    "{:?}",
+                    e.context("Could not reconcile daemon state with Docker containers")
+                );
+            }
+        }
+
         // Update the Juju status
         crate::juju::set_status(tools::get_juju_status(&daemon.state.read().unwrap()))
             .unwrap_or_else(|e| {
@@ -128,37 +200,31 @@ impl LuckyDaemon {
 
         log::trace!("Loaded daemon state: {:#?}", daemon.state.read().unwrap());
 
-        daemon
-    }
-
-    /// Gets a handle to the daemon's Docker connection, creating a new one if one doesn't already
-    /// exist.
-    fn get_docker_conn(&self) -> anyhow::Result<Arc<Mutex<Docker>>> {
-        let mut docker_conn = self.docker_conn.lock().unwrap();
-
-        // If we have a connection already, return it
-        if let Some(docker_conn) = &*docker_conn {
-            Ok(docker_conn.clone())
-        // If there is no connection
-        } else {
-            // Connect to docker
-            log::debug!("Connecting to Docker");
-            let conn = Docker::new();
-
-            // Test getting Docker info
-            log::trace!("Docker info: {:?}", crate::rt::block_on(conn.info())?);
+        // Resume forwarding container logs
+        if daemon.lucky_metadata.use_docker && daemon.lucky_metadata.forward_container_logs {
+            tools::forward_all_container_logs(&daemon)
+                .context("Could not forward container logs")
+                .unwrap_or_else(|e| log::warn!("{:?}", e));
+        }
 
-            // Return connection
-            let conn = Arc::new(Mutex::new(conn));
-            *docker_conn = Some(conn.clone());
-            Ok(conn)
+        // Watch for containers exiting
+        if daemon.lucky_metadata.use_docker {
+            events::spawn_event_watcher(
+                daemon.runtime.clone(),
+                daemon.state.clone(),
+                daemon.event_suppression.clone(),
+                daemon.stop_listening.clone(),
+            )
+            .context("Could not start Docker event watcher")
+            .unwrap_or_else(|e| log::warn!("{:?}", e));
         }
+
+        daemon
     }
 
     #[allow(clippy::needless_pass_by_value)]
     fn _trigger_hook(
         &self,
-        call: &mut dyn rpc::Call_TriggerHook,
         hook_name: &str,
         environment: &HashMap<String, String>,
     ) -> anyhow::Result<()> {
@@ -168,6 +234,22 @@ impl LuckyDaemon {
             hook_name
         ))?;
 
+        // Apply the containers declared in the lucky.yaml, unless the charm is being removed
+        let containers_changed =
+            if self.lucky_metadata.use_docker && hook_name != "stop" && hook_name != "remove" {
+                let mut state = self.state.write().unwrap();
+                container_definitions::apply_container_definitions(self, &mut state)
+                    .context("Could not apply the containers declared in the lucky.yaml")?
+            } else {
+                false
+            };
+
+        // Deploy changes to the declared containers before any scripts run so that scripts can use
+        // newly declared containers
+        if containers_changed {
+            tools::apply_container_updates(self)?;
+        }
+
         // Create a mutable clone of the recieved environment
         let mut environment = environment.clone();
 
@@ -237,9 +319,6 @@ impl LuckyDaemon {
             hook_name
         ))?;
 
-        // Reply empty
-        call.reply()?;
-
         Ok(())
     }
 }
@@ -404,8 +483,11 @@ impl rpc::VarlinkInterface for LuckyDaemon {
 
         log::info!("Triggering hook: {}", hook_name);
 
+        // Update the proxy settings, which are only available in the hook environment
+        handle_err!(tools::update_proxy_settings(self, &environment), call);
+
         // Trigger hook
-        handle_err!(self._trigger_hook(call, &hook_name, &environment), call);
+        handle_err!(self._trigger_hook(&hook_name, &environment), call);
 
         // Unset the hook environment variables as they will be invalid when the hook exits
         for var in environment.keys() {
@@ -414,7 +496,96 @@ impl rpc::VarlinkInterface for LuckyDaemon {
 
         log::info!("Done triggering hook: {}", hook_name);
 
-        Ok(())
+        // Reply empty
+        call.reply()
+    }
+
+    /// Handle a container exiting and run the `container-died` scripts
+    fn container_died(
+        &self,
+        call: &mut dyn rpc::Call_ContainerDied,
+        juju_context_id: String,
+        container_name: Option<String>,
+        exit_code: i64,
+        oom_killed: bool,
+    ) -> varlink::Result<()> {
+        // Set the Juju context
+        std::env::set_var("JUJU_CONTEXT_ID", &juju_context_id);
+
+        let container_log_name = container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME);
+        log::info!(
+            r#"Handling exit of container "{}" with code {}"#,
+            container_log_name,
+            exit_code
+        );
+
+        // Set the container's status
+        handle_err!(
+            tools::set_script_status(
+                &mut self.state.write().unwrap(),
+                &tools::container_status_id(container_name.as_deref()),
+                ScriptStatus {
+                    state: ScriptState::Blocked,
+                    message: Some(if oom_killed {
+                        format!("container {} ran out of memory", container_log_name)
+                    } else {
+                        format!("container {} exited {}", container_log_name, exit_code)
+                    }),
+                }
+            ),
+            call
+        );
+
+        // Create environment map
+        let mut environment: HashMap<String, String> = HashMap::new();
+        environment.insert("JUJU_CONTEXT_ID".into(), juju_context_id);
+        environment.insert(
+            "LUCKY_CONTAINER_NAME".into(),
+            container_name.unwrap_or_else(|| "".into()),
+        );
+        environment.insert("LUCKY_CONTAINER_EXIT_CODE".into(), exit_code.to_string());
+        environment.insert("LUCKY_CONTAINER_OOM_KILLED".into(), oom_killed.to_string());
+
+        // Run the `container-died` scripts
+        handle_err!(self._trigger_hook("container-died", &environment), call);
+
+        // Unset the Juju context as it will be invalid when the container-died command exits
+        std::env::remove_var("JUJU_CONTEXT_ID");
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Handle a container that exited being started again
+    fn container_started(
+        &self,
+        call: &mut dyn rpc::Call_ContainerStarted,
+        juju_context_id: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        // Set the Juju context
+        std::env::set_var("JUJU_CONTEXT_ID", &juju_context_id);
+
+        let mut state = self.state.write().unwrap();
+
+        // Clear the container's status
+        let status_id = tools::container_status_id(container_name.as_deref());
+        if state.script_statuses.remove(&status_id).is_some() {
+            log::info!(
+                r#"Clearing status of restarted container "{}""#,
+                container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME)
+            );
+            handle_err!(
+                crate::juju::set_status(tools::get_juju_status(&state)),
+                call
+            );
+        }
+
+        // Unset the Juju context as it will be invalid when the container-started command exits
+        std::env::remove_var("JUJU_CONTEXT_ID");
+
+        // Reply empty
+        call.reply()
     }
 
     /// Set a script's status
@@ -660,6 +831,89 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         call.reply()
     }
 
+    fn container_exec(
+        &self,
+        call: &mut dyn rpc::Call_ContainerExec,
+        command: Vec<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let container_log_name = container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME);
+
+        // Get the container ID. This must be scoped so that we don't hold the state lock while
+        // the command is running, otherwise a command that uses the Lucky client would deadlock.
+        let container_id = {
+            let state = self.state.read().unwrap();
+            handle_err!(
+                tools::get_container_id(&state, container_name.as_deref()),
+                call
+            )
+        };
+
+        log::debug!(
+            r#"Executing command in container "{}": {:?}"#,
+            container_log_name,
+            command
+        );
+
+        // Run the command
+        let output = handle_err!(
+            tools::exec_in_container(&*self.runtime, &container_id, command, vec![]),
+            call
+        );
+
+        call.reply(output.exit_code, output.stdout, output.stderr)
+    }
+
+    fn container_copy_to(
+        &self,
+        call: &mut dyn rpc::Call_ContainerCopyTo,
+        source: String,
+        target: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let container_id = {
+            let state = self.state.read().unwrap();
+            handle_err!(
+                tools::get_container_id(&state, container_name.as_deref()),
+                call
+            )
+        };
+
+        // Copy the file into the container
+        handle_err!(
+            tools::copy_to_container(&*self.runtime, &container_id, Path::new(&source), &target),
+            call
+        );
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_copy_from(
+        &self,
+        call: &mut dyn rpc::Call_ContainerCopyFrom,
+        source: String,
+        target: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let container_id = {
+            let state = self.state.read().unwrap();
+            handle_err!(
+                tools::get_container_id(&state, container_name.as_deref()),
+                call
+            )
+        };
+
+        // Copy the file out of the container
+        handle_err!(
+            tools::copy_from_container(&*self.runtime, &container_id, &source, Path::new(&target)),
+            call
+        );
+
+        // Reply empty
+        call.reply()
+    }
+
     fn container_set_entrypoint(
         &self,
         call: &mut dyn rpc::Call_ContainerSetEntrypoint,
@@ -742,26 +996,44 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         call: &mut dyn rpc::Call_ContainerImageSet,
         image: String,
         container_name: Option<String>,
-        no_pull: bool,
+        pull_policy: Option<String>,
+        pin: bool,
+        registry_auth_config_key: Option<String>,
     ) -> varlink::Result<()> {
         let mut state = self.state.write().unwrap();
 
+        // Parse the pull policy
+        let pull_policy: PullPolicy = match pull_policy {
+            Some(policy) => handle_err!(
+                policy
+                    .parse()
+                    .context(format!("Invalid pull policy: {}", policy)),
+                call
+            ),
+            None => Default::default(),
+        };
+
         // If this is for a named container
-        if let Some(name) = container_name {
-            if let Some(container) = state.named_containers.get_mut(&name) {
+        if let Some(name) = &container_name {
+            if let Some(container) = state.named_containers.get_mut(name) {
                 log::debug!("Set Docker image [{}]: {}", name, image);
                 // Set the image on existing container
                 container.update(|c| {
                     c.config.image = image;
-                    c.pull_image = !no_pull;
+                    c.pull_policy = pull_policy;
+                    c.registry_auth_config_key = registry_auth_config_key;
+                    c.image_build = None;
                 });
             } else {
                 log::debug!("Adding new docker container: {}", name);
                 log::debug!("Set Docker image [{}]: {}", name, image);
                 // Create a new container with the given image
                 let mut new_container = ContainerInfo::new(&image);
-                new_container.pull_image = !no_pull;
-                state.named_containers.insert(name, new_container.into());
+                new_container.pull_policy = pull_policy;
+                new_container.registry_auth_config_key = registry_auth_config_key;
+                state
+                    .named_containers
+                    .insert(name.clone(), new_container.into());
             }
         // If this is for the default container
         } else {
@@ -770,24 +1042,33 @@ impl rpc::VarlinkInterface for LuckyDaemon {
                 // Set the image on existing container
                 container.update(|c| {
                     c.config.image = image;
-                    c.pull_image = !no_pull;
+                    c.pull_policy = pull_policy;
+                    c.registry_auth_config_key = registry_auth_config_key;
+                    c.image_build = None;
                 });
             } else {
                 log::debug!("Adding container");
                 log::debug!("Set container image: {}", image);
                 // Create a new container with the given image
                 let mut new_container = ContainerInfo::new(&image);
-                new_container.pull_image = !no_pull;
+                new_container.pull_policy = pull_policy;
+                new_container.registry_auth_config_key = registry_auth_config_key;
                 state.default_container = Some(new_container.into());
             }
         }
 
+        // Pin the image to its digest
+        if pin {
+            handle_err!(
+                tools::pin_container_image(&self, &mut state, container_name.as_deref()),
+                call
+            );
+        }
+
         // Reply empty
         call.reply()
     }
 
-    // The uncollapsed if is easier to understand in this case
-    #[allow(clippy::collapsible_if)]
     fn container_image_get(
         &self,
         call: &mut dyn rpc::Call_ContainerImageGet,
@@ -795,21 +1076,181 @@ impl rpc::VarlinkInterface for LuckyDaemon {
     ) -> varlink::Result<()> {
         let state = self.state.read().unwrap();
 
-        // If this is for a named container
-        if let Some(name) = container_name {
-            if let Some(container) = state.named_containers.get(&name) {
-                call.reply(Some(container.config.image.clone()))
-            } else {
-                call.reply(None)
-            }
-        // If this is for the default container
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // If the specified container exists
+        if let Some(container) = container {
+            call.reply(
+                Some(container.config.image.clone()),
+                container.image_digest.clone(),
+            )
         } else {
-            if let Some(container) = &state.default_container {
-                call.reply(Some(container.config.image.clone()))
-            } else {
-                call.reply(None)
+            call.reply(None, None)
+        }
+    }
+
+    fn container_image_build(
+        &self,
+        call: &mut dyn rpc::Call_ContainerImageBuild,
+        image_name: String,
+        tag: Option<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        // Build the image. The state must not be locked during the build because it can take a
+        // long time and the daemon would not be able to handle any other requests.
+        let image_build = handle_err!(
+            tools::build_charm_image(&self, &image_name, tag.as_deref()),
+            call
+        );
+        let image_id = image_build.image_id.clone();
+
+        // Set the image on the container
+        let mut state = self.state.write().unwrap();
+        tools::set_container_image_build(&mut state, container_name.as_deref(), image_build);
+
+        call.reply(image_id)
+    }
+
+    fn container_image_prune(
+        &self,
+        call: &mut dyn rpc::Call_ContainerImagePrune,
+        keep_last: Option<i64>,
+    ) -> varlink::Result<()> {
+        let keep_last = match keep_last {
+            Some(keep_last) if keep_last < 0 => {
+                return call.reply_error("keep_last must not be negative".into())
             }
+            Some(keep_last) => keep_last as usize,
+            None => self.lucky_metadata.image_cleanup.keep_last,
+        };
+
+        // Get the unused images. This is scoped so that we don't hold the state lock while the
+        // images are removed.
+        let images = {
+            let mut state = self.state.write().unwrap();
+
+            // Make sure that the images the containers are running are tracked
+            handle_err!(
+                tools::track_container_images(&*self.runtime, &mut state),
+                call
+            );
+
+            handle_err!(
+                tools::images_to_prune(&*self.runtime, &state, keep_last),
+                call
+            )
+        };
+
+        // Remove the unused images
+        let pruned = tools::prune_images(&*self.runtime, images);
+
+        // Stop tracking the images that were handled, even if some of them could not be removed
+        let mut state = self.state.write().unwrap();
+        tools::untrack_images(&mut state, &pruned.untracked);
+        handle_err!(pruned.error.map_or(Ok(()), Err), call);
+
+        call.reply(
+            pruned
+                .removed
+                .into_iter()
+                .map(|image| image.image)
+                .collect(),
+        )
+    }
+
+    fn container_registry_login(
+        &self,
+        call: &mut dyn rpc::Call_ContainerRegistryLogin,
+        registry: String,
+        username: Option<String>,
+        password: Option<String>,
+        identity_token: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Make sure the credentials are usable
+        let credentials = RegistryCredentials {
+            username,
+            password,
+            identity_token,
+            server_address: None,
+        };
+        handle_err!(credentials.to_registry_auth(&registry), call);
+
+        log::debug!("Logging in to container registry: {}", registry);
+        state.registry_credentials.insert(registry, credentials);
+
+        call.reply()
+    }
+
+    fn container_registry_logout(
+        &self,
+        call: &mut dyn rpc::Call_ContainerRegistryLogout,
+        registry: String,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        log::debug!("Logging out of container registry: {}", registry);
+        state.registry_credentials.remove(&registry);
+
+        call.reply()
+    }
+
+    fn container_set_update_strategy(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetUpdateStrategy,
+        strategy: String,
+        health_check_timeout: Option<i64>,
+        startup_grace_period: Option<i64>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Parse the update strategy
+        let strategy: UpdateStrategy = handle_err!(
+            strategy
+                .parse()
+                .context(format!("Invalid update strategy: {}", strategy)),
+            call
+        );
+        let health_check_timeout = handle_err!(
+            health_check_timeout
+                .map(|timeout| timeout.try_into().context("Invalid health check timeout"))
+                .transpose(),
+            call
+        );
+        let startup_grace_period = handle_err!(
+            startup_grace_period
+                .map(|period| period.try_into().context("Invalid startup grace period"))
+                .transpose(),
+            call
+        );
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container update strategy{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                strategy.as_ref()
+            );
+
+            // The update strategy doesn't change the Docker container, so update it without
+            // marking the container as needing to be re-created
+            container.update_untracked(|c| {
+                c.update_strategy = strategy;
+                c.health_check_timeout = health_check_timeout;
+                c.startup_grace_period = startup_grace_period;
+            });
         }
+
+        // Reply empty
+        call.reply()
     }
 
     fn container_env_get(
@@ -922,28 +1363,37 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         source: String,
         target: String,
         container_name: Option<String>,
+        options: Option<rpc::MountOptions>,
     ) -> varlink::Result<()> {
         let mut state = self.state.write().unwrap();
 
-        // Get the config for the requested container
-        let mut container_log_name = None;
-        let mut container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
+        // Parse the mount options
+        let options: Option<MountOptions> = match options {
+            Some(options) => Some(handle_err!(options.try_into(), call)),
+            None => None,
         };
 
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
         if let Some(container) = &mut container {
             log::debug!(
                 "Creating container volume{}: {}:{}",
-                container_log_name.map_or("".into(), |x| format!("[{}]", x)),
+                container_log_suffix(container_name.as_deref()),
                 source,
                 target
             );
             // Add volume to container config
             container.update(|c| {
+                // Set the mount options, removing any old options if there are none
+                if let Some(options) = options {
+                    c.config
+                        .mount_options
+                        .insert(VolumeTarget(target.clone()), options);
+                } else {
+                    c.config.mount_options.remove(&VolumeTarget(target.clone()));
+                }
+
                 c.config
                     .volumes
                     .insert(VolumeTarget(target), VolumeSource(source));
@@ -964,20 +1414,13 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         let mut state = self.state.write().unwrap();
 
         // Get the config for the requested container
-        let mut container_log_name = None;
-        let mut container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
-        };
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
 
         // If the specified container exists
         if let Some(container) = &mut container {
             log::debug!(
                 "Deleting container volume{}: {}",
-                container_log_name.map_or("".into(), |x| format!("[{}]", x)),
+                container_log_suffix(container_name.as_deref()),
                 target
             );
 
@@ -985,27 +1428,60 @@ impl rpc::VarlinkInterface for LuckyDaemon {
             container.update(|container| {
                 let volumes = &mut container.config.volumes;
 
-                // Get source and remove from volume list
-                let source = volumes.remove(&VolumeTarget(target));
+                // Get source and mount type and remove from volume list
+                let target = VolumeTarget(target);
+                let source = volumes.remove(&target);
+                let mount_type = container
+                    .config
+                    .mount_options
+                    .remove(&target)
+                    .map_or(MountType::Bind, |options| options.mount_type);
 
                 // If there is a volume for the given target path
                 if let Some(source) = source {
                     // If we should delete the source data
                     if delete_data {
                         // If there are no other volumes with the same source
-                        if volumes.values().find(|&x| *x == source).is_none() {
+                        let mount_options = &container.config.mount_options;
+                        if !volumes.iter().any(|(other_target, other_source)| {
+                            *other_source == source
+                                && mount_options
+                                    .get(other_target)
+                                    .map_or(MountType::Bind, |options| options.mount_type)
+                                    == mount_type
+                        }) {
                             log::debug!("Deleting volume data source: {}", &*source);
 
                             // Delete data
-                            if source.starts_with('/') {
-                                handle_err!(std::fs::remove_dir_all(&*source), call);
-                            } else {
-                                handle_err!(
-                                    std::fs::remove_dir_all(
-                                        self.lucky_data_dir.join(VOLUME_DIR).join(&*source)
-                                    ),
-                                    call
-                                );
+                            match mount_type {
+                                MountType::Bind => {
+                                    if source.starts_with('/') {
+                                        handle_err!(std::fs::remove_dir_all(&*source), call);
+                                    } else {
+                                        handle_err!(
+                                            std::fs::remove_dir_all(
+                                                self.lucky_data_dir.join(VOLUME_DIR).join(&*source)
+                                            ),
+                                            call
+                                        );
+                                    }
+                                }
+                                // Docker volumes can't be removed until the container stops
+                                // using them so we remove them on the next container update
+                                MountType::Volume => {
+                                    let unit_name = handle_err!(
+                                        std::env::var("JUJU_UNIT_NAME")
+                                            .context("Env var JUJU_UNIT_NAME not readable!"),
+                                        call
+                                    );
+                                    container.pending_volume_removals.insert(
+                                        crate::docker::docker_volume_name(&unit_name, &source),
+                                    );
+                                }
+                                // Tmpfs mounts don't have any data to delete
+                                MountType::Tmpfs => {
+                                    return call.reply(false /* no data deleted */);
+                                }
                             }
 
                             call.reply(true /* data deleted */)?;
@@ -1048,6 +1524,12 @@ impl rpc::VarlinkInterface for LuckyDaemon {
                         |(target, source)| rpc::ContainerVolumeGetAll_Reply_volumes {
                             source: (**source).clone(),
                             target: (**target).clone(),
+                            options: container
+                                .config
+                                .mount_options
+                                .get(target)
+                                .cloned()
+                                .map(Into::into),
                         },
                     )
                     .collect(),
@@ -1061,56 +1543,28 @@ impl rpc::VarlinkInterface for LuckyDaemon {
     fn container_port_add(
         &self,
         call: &mut dyn rpc::Call_ContainerPortAdd,
-        host_port: i64,
-        container_port: i64,
-        protocol: String,
+        port_binding: rpc::PortBinding,
+        expose: bool,
         container_name: Option<String>,
     ) -> varlink::Result<()> {
         let mut state = self.state.write().unwrap();
+        let port_binding = handle_err!(PortBinding::try_from(port_binding), call);
 
         // Get the config for the requested container
-        let mut container_log_name = None;
-        let mut container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
-        };
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
 
         if let Some(container) = &mut container {
             log::debug!(
-                "Adding port to container{}: {}:{}/{}",
-                container_log_name.map_or("".into(), |x| format!("[{}]", x)),
-                host_port,
-                container_port,
-                protocol
+                "Adding port to container{}: {}{}",
+                container_log_suffix(container_name.as_deref()),
+                port_binding,
+                if expose { " ( exposed )" } else { "" }
             );
 
-            let host_port = handle_err!(host_port.try_into().context("Invalid port number"), call);
-            let container_port = handle_err!(
-                container_port.try_into().context("Invalid port number"),
-                call
-            );
-
-            let port_binding = PortBinding {
-                host_port,
-                container_port,
-                protocol,
-            };
-
-            // If there are other port bindings with the same protocol and host or container port
-            // but isn't the exact same port binding
-            if let Some(offending_binding) = container.config.ports.iter().find(|&b| {
-                // With the same host port
-                (b.host_port == port_binding.host_port
-                        // or with the same container port
-                        || b.container_port == port_binding.container_port)
-                        // and with the same protocol
-                        && b.protocol == port_binding.protocol
-                        // and not the same exact port binding
-                        && b != &port_binding
-            }) {
+            // If there are other port bindings that bind the same host or container ports
+            if let Some(offending_binding) =
+                container.config.conflicting_port_binding(&port_binding)
+            {
                 // Throw an error because we can't add port binding that has the same port as
                 // another.
                 call.reply_error(format!(
@@ -1123,9 +1577,22 @@ impl rpc::VarlinkInterface for LuckyDaemon {
                 return Ok(());
             }
 
-            container.update(|c| {
-                c.config.ports.insert(port_binding);
-            });
+            // Only re-create the container if the port binding is new
+            if !container.config.ports.contains(&port_binding) {
+                container.update(|c| c.config.ports.insert(port_binding.clone()));
+            }
+
+            // Exposing the port only changes which Juju ports are opened
+            if container.exposed_ports.contains(&port_binding) != expose {
+                container.update_untracked(|c| {
+                    if expose {
+                        c.exposed_ports.insert(port_binding.clone());
+                    } else {
+                        c.exposed_ports.remove(&port_binding);
+                    }
+                });
+                handle_err!(tools::reconcile_exposed_ports(&mut state), call);
+            }
         }
 
         // Reply empty
@@ -1135,47 +1602,40 @@ impl rpc::VarlinkInterface for LuckyDaemon {
     fn container_port_remove(
         &self,
         call: &mut dyn rpc::Call_ContainerPortRemove,
-        host_port: i64,
-        container_port: i64,
-        protocol: String,
+        port_binding: rpc::PortBinding,
         container_name: Option<String>,
     ) -> varlink::Result<()> {
         let mut state = self.state.write().unwrap();
+        let mut port_binding = handle_err!(PortBinding::try_from(port_binding), call);
 
         // Get the config for the requested container
-        let mut container_log_name = None;
-        let mut container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
-        };
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
 
         if let Some(container) = &mut container {
             log::debug!(
-                "Removing port from container{}: {}:{}/{}",
-                container_log_name.map_or("".into(), |x| format!("[{}]", x)),
-                host_port,
-                container_port,
-                protocol
+                "Removing port from container{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                port_binding
             );
 
-            container.update(|c| {
-                c.config.ports.remove(&PortBinding {
-                    host_port: handle_err!(
-                        host_port.try_into().context("Invalid port number"),
-                        call
-                    ),
-                    container_port: handle_err!(
-                        container_port.try_into().context("Invalid port number"),
-                        call
-                    ),
-                    protocol,
-                });
+            // Allow port bindings with an allocated host port to be removed by the host port
+            // that was allocated
+            if !container.config.ports.contains(&port_binding) {
+                if let Some(binding) = container.config.ports.iter().find(|&b| {
+                    b.host_port == 0
+                        && PortBinding {
+                            host_port: container.config.host_port(b),
+                            ..b.clone()
+                        } == port_binding
+                }) {
+                    port_binding = binding.clone();
+                }
+            }
 
-                Ok(())
-            })?;
+            container.update(|c| {
+                c.config.ports.remove(&port_binding);
+                c.exposed_ports.remove(&port_binding);
+            });
         }
 
         // Reply empty
@@ -1190,31 +1650,21 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         let mut state = self.state.write().unwrap();
 
         // Get the config for the requested container
-        let mut container_log_name = None;
-        let container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
-        };
+        let container = get_container_mut(&mut state, container_name.as_deref());
 
         if let Some(container) = container {
             // For each port
             for port_binding in &container.config.ports.clone() {
                 log::debug!(
-                    "Removing port from container{}: {}:{}/{}",
-                    container_log_name
-                        .as_ref()
-                        .map_or("".into(), |x| format!("[{}]", x)),
-                    port_binding.host_port,
-                    port_binding.container_port,
-                    port_binding.protocol
+                    "Removing port from container{}: {}",
+                    container_log_suffix(container_name.as_deref()),
+                    port_binding
                 );
 
                 // Remove the port
                 container.update(|c| {
                     c.config.ports.remove(&port_binding);
+                    c.exposed_ports.remove(&port_binding);
                 });
             }
         }
@@ -1242,10 +1692,12 @@ impl rpc::VarlinkInterface for LuckyDaemon {
                     .config
                     .ports
                     .iter()
-                    .map(|port| rpc::ContainerPortGetAll_Reply_ports {
-                        container_port: port.container_port.into(),
-                        host_port: port.host_port.into(),
-                        protocol: port.protocol.clone(),
+                    .map(|port| {
+                        PortBinding {
+                            host_port: container.config.host_port(port),
+                            ..port.clone()
+                        }
+                        .into()
                     })
                     .collect(),
             )
@@ -1255,6 +1707,37 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         }
     }
 
+    /// Set whether or not all of the container's host ports are opened through Juju
+    fn container_set_auto_expose(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetAutoExpose,
+        auto_expose: bool,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container auto-expose{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                auto_expose,
+            );
+
+            // Exposing the ports only changes which Juju ports are opened, so the container
+            // doesn't need to be re-created
+            if container.auto_expose != auto_expose {
+                container.update_untracked(|c| c.auto_expose = auto_expose);
+                handle_err!(tools::reconcile_exposed_ports(&mut state), call);
+            }
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
     fn container_network_set(
         &self,
         call: &mut dyn rpc::Call_ContainerNetworkSet,
@@ -1264,19 +1747,12 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         let mut state = self.state.write().unwrap();
 
         // Get the config for the requested container
-        let mut container_log_name = None;
-        let mut container = match &container_name {
-            Some(container_name) => {
-                container_log_name = Some(container_name.clone());
-                state.named_containers.get_mut(container_name)
-            }
-            None => state.default_container.as_mut(),
-        };
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
 
         if let Some(container) = &mut container {
             log::debug!(
                 "Setting container network{}: {}",
-                container_log_name.map_or("".into(), |x| format!("[{}]", x)),
+                container_log_suffix(container_name.as_deref()),
                 network_name.as_ref().unwrap_or(&"unset".to_string()),
             );
 
@@ -1286,6 +1762,986 @@ impl rpc::VarlinkInterface for LuckyDaemon {
         // Reply empty
         call.reply()
     }
+
+    fn container_network_add(
+        &self,
+        call: &mut dyn rpc::Call_ContainerNetworkAdd,
+        network_name: String,
+        aliases: Vec<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Make sure the network exists
+        if !state.networks.contains_key(&network_name) {
+            return call.reply_error(format!(
+                "Network \"{}\" does not exist. Create it with `lucky network create`.",
+                network_name
+            ));
+        }
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Attaching container to network{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                network_name
+            );
+
+            container.update(|c| {
+                c.config
+                    .networks
+                    .insert(network_name, aliases.into_iter().collect())
+            });
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_network_remove(
+        &self,
+        call: &mut dyn rpc::Call_ContainerNetworkRemove,
+        network_name: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Detaching container from network{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                network_name
+            );
+
+            container.update(|c| c.config.networks.remove(&network_name));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_network_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerNetworkGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // If the container exists
+        if let Some(container) = container {
+            call.reply(
+                container
+                    .config
+                    .networks
+                    .iter()
+                    .map(
+                        |(name, aliases)| rpc::ContainerNetworkGetAll_Reply_networks {
+                            name: name.clone(),
+                            aliases: aliases.iter().cloned().collect(),
+                        },
+                    )
+                    .collect(),
+            )
+        } else {
+            // Reply empty
+            call.reply(vec![])
+        }
+    }
+
+    fn container_dependency_add(
+        &self,
+        call: &mut dyn rpc::Call_ContainerDependencyAdd,
+        dependency: String,
+        wait_for_healthy: bool,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // A container cannot depend on itself
+        if container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME) == dependency {
+            return call.reply_error(format!(
+                r#"Container "{}" cannot depend on itself"#,
+                dependency
+            ));
+        }
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Adding container dependency{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                dependency
+            );
+
+            // Only update the container if the dependency has changed
+            if container.depends_on.get(&dependency) != Some(&wait_for_healthy) {
+                let old_value = container.depends_on.get(&dependency).cloned();
+                container.update(|c| c.depends_on.insert(dependency.clone(), wait_for_healthy));
+
+                // Undo the change if it introduced a dependency cycle
+                if let Err(e) = tools::container_start_order(&state) {
+                    let container = get_container_mut(&mut state, container_name.as_deref())
+                        .expect("Missing container");
+                    container.update(|c| match old_value {
+                        Some(value) => c.depends_on.insert(dependency, value),
+                        None => c.depends_on.remove(&dependency),
+                    });
+
+                    return call.reply_error(format!("{:?}", e));
+                }
+            }
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_dependency_remove(
+        &self,
+        call: &mut dyn rpc::Call_ContainerDependencyRemove,
+        dependency: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Removing container dependency{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                dependency
+            );
+
+            if container.depends_on.contains_key(&dependency) {
+                container.update(|c| c.depends_on.remove(&dependency));
+            }
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_dependency_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerDependencyGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // If the container exists
+        if let Some(container) = container {
+            let mut dependencies: Vec<_> = container
+                .depends_on
+                .iter()
+                .map(|(name, &wait_for_healthy)| {
+                    rpc::ContainerDependencyGetAll_Reply_dependencies {
+                        name: name.clone(),
+                        wait_for_healthy,
+                    }
+                })
+                .collect();
+            dependencies.sort_by(|a, b| a.name.cmp(&b.name));
+
+            call.reply(dependencies)
+        } else {
+            // Reply empty
+            call.reply(vec![])
+        }
+    }
+
+    fn container_init_add(
+        &self,
+        call: &mut dyn rpc::Call_ContainerInitAdd,
+        image: String,
+        entrypoint: Option<String>,
+        command: Option<Vec<String>>,
+        env: HashMap<String, String>,
+        volumes: Vec<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Build the init container config
+        let init_config = handle_err!(
+            container_definitions::job_container_config(&JobContainerDefinition {
+                image,
+                entrypoint,
+                command,
+                env,
+                volumes,
+                ..Default::default()
+            }),
+            call
+        );
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Adding init container{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                init_config.image
+            );
+
+            container.update(|c| c.init_containers.push(init_config));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_init_remove(
+        &self,
+        call: &mut dyn rpc::Call_ContainerInitRemove,
+        index: i64,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            let index = match usize::try_from(index) {
+                Ok(index) if index < container.init_containers.len() => index,
+                _ => {
+                    return call.reply_error(format!(
+                        "Container has no init container at index {}",
+                        index
+                    ))
+                }
+            };
+
+            log::debug!(
+                "Removing init container{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                container.init_containers[index].image
+            );
+
+            container.update(|c| c.init_containers.remove(index));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn container_init_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerInitGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // If the container exists
+        if let Some(container) = container {
+            call.reply(
+                container
+                    .init_containers
+                    .iter()
+                    .map(
+                        |init_config| rpc::ContainerInitGetAll_Reply_init_containers {
+                            image: init_config.image.clone(),
+                            entrypoint: init_config.entrypoint.clone(),
+                            command: init_config.command.clone(),
+                        },
+                    )
+                    .collect(),
+            )
+        } else {
+            // Reply empty
+            call.reply(vec![])
+        }
+    }
+
+    /// Set container resource limits
+    fn container_limits_set(
+        &self,
+        call: &mut dyn rpc::Call_ContainerLimitsSet,
+        limits: HashMap<String, Option<String>>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            // Validate the limits before updating the container
+            let mut new_limits = container.config.limits.clone();
+            for (name, value) in &limits {
+                handle_err!(new_limits.set(name, value.as_deref()), call);
+            }
+
+            log::debug!(
+                "Setting container limits{}: {:?}",
+                container_log_suffix(container_name.as_deref()),
+                limits
+            );
+
+            container.update(|c| c.config.limits = new_limits);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Get container resource limits
+    fn container_limits_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerLimitsGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // Reply with the limits or an empty list if the container doesn't exist
+        call.reply(container.map_or(vec![], |container| {
+            container
+                .config
+                .limits
+                .get_all()
+                .into_iter()
+                .map(|(name, value)| rpc::ContainerLimitsGetAll_Reply_limits { name, value })
+                .collect()
+        }))
+    }
+
+    /// Set the container user
+    fn container_set_user(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetUser,
+        user: Option<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container user{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                user.as_ref().unwrap_or(&"unset".to_string()),
+            );
+
+            container.update(|c| c.config.user = user);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Set the container working directory
+    fn container_set_working_dir(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetWorkingDir,
+        working_dir: Option<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container working dir{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                working_dir.as_ref().unwrap_or(&"unset".to_string()),
+            );
+
+            container.update(|c| c.config.working_dir = working_dir);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Set the container hostname
+    fn container_set_hostname(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetHostname,
+        hostname: Option<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container hostname{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                hostname.as_ref().unwrap_or(&"unset".to_string()),
+            );
+
+            container.update(|c| c.config.hostname = hostname);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Set whether or not the container is privileged
+    fn container_set_privileged(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetPrivileged,
+        privileged: bool,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container privileged mode{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                privileged,
+            );
+
+            container.update(|c| c.config.privileged = privileged);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Set the size of the container's `/dev/shm`
+    fn container_set_shm_size(
+        &self,
+        call: &mut dyn rpc::Call_ContainerSetShmSize,
+        shm_size: Option<String>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Parse the size
+        let shm_size = handle_err!(
+            shm_size
+                .as_ref()
+                .map(|size| crate::docker::parse_byte_size(size))
+                .transpose(),
+            call
+        );
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Setting container shm size{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                shm_size.map_or("unset".to_string(), |x| x.to_string()),
+            );
+
+            container.update(|c| c.config.shm_size = shm_size);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Set container labels
+    fn container_label_set(
+        &self,
+        call: &mut dyn rpc::Call_ContainerLabelSet,
+        labels: HashMap<String, Option<String>>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Make sure none of the labels are reserved
+        if let Some(key) = labels.keys().find(|key| key.starts_with("lucky.")) {
+            return call.reply_error(format!(
+                r#"Cannot set label "{}": labels starting with "lucky." are reserved"#,
+                key
+            ));
+        }
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            // For every label
+            for (key, value) in labels {
+                log::debug!(
+                    "Setting container label{}: {} = {}",
+                    container_log_suffix(container_name.as_deref()),
+                    key,
+                    value.as_ref().unwrap_or(&"unset".to_string())
+                );
+
+                if let Some(value) = value {
+                    // Set the label
+                    container.update(|c| c.config.labels.insert(key, value));
+                } else {
+                    // Remove the label
+                    container.update(|c| c.config.labels.remove(&key));
+                }
+            }
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Get all of the custom container labels
+    fn container_label_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerLabelGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // Reply with the labels or an empty list if the container doesn't exist
+        call.reply(container.map_or(vec![], |container| {
+            container
+                .config
+                .labels
+                .iter()
+                .map(|(key, value)| rpc::ContainerLabelGetAll_Reply_labels {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect()
+        }))
+    }
+
+    /// Add a capability to the container
+    fn container_capability_add(
+        &self,
+        call: &mut dyn rpc::Call_ContainerCapabilityAdd,
+        capability: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Adding container capability{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                capability
+            );
+
+            container.update(|c| c.config.cap_add.insert(capability));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Remove a capability from the container
+    fn container_capability_remove(
+        &self,
+        call: &mut dyn rpc::Call_ContainerCapabilityRemove,
+        capability: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Removing container capability{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                capability
+            );
+
+            container.update(|c| c.config.cap_add.remove(&capability));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Get the capabilities added to the container
+    fn container_capability_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerCapabilityGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // Reply with the capabilities or an empty list if the container doesn't exist
+        call.reply(container.map_or(vec![], |container| {
+            container.config.cap_add.iter().cloned().collect()
+        }))
+    }
+
+    /// Add an entry to the container's `/etc/hosts` file
+    fn container_extra_host_add(
+        &self,
+        call: &mut dyn rpc::Call_ContainerExtraHostAdd,
+        hostname: String,
+        ip: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Adding container extra host{}: {} -> {}",
+                container_log_suffix(container_name.as_deref()),
+                hostname,
+                ip
+            );
+
+            container.update(|c| c.config.extra_hosts.insert(hostname, ip));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Remove an entry from the container's `/etc/hosts` file
+    fn container_extra_host_remove(
+        &self,
+        call: &mut dyn rpc::Call_ContainerExtraHostRemove,
+        hostname: String,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Get the config for the requested container
+        let mut container = get_container_mut(&mut state, container_name.as_deref());
+
+        if let Some(container) = &mut container {
+            log::debug!(
+                "Removing container extra host{}: {}",
+                container_log_suffix(container_name.as_deref()),
+                hostname
+            );
+
+            container.update(|c| c.config.extra_hosts.remove(&hostname));
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    /// Get the extra hosts added to the container
+    fn container_extra_host_get_all(
+        &self,
+        call: &mut dyn rpc::Call_ContainerExtraHostGetAll,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        // Get the config for the requested container
+        let container = match &container_name {
+            Some(container_name) => state.named_containers.get(container_name),
+            None => state.default_container.as_ref(),
+        };
+
+        // Reply with the hosts or an empty list if the container doesn't exist
+        call.reply(container.map_or(vec![], |container| {
+            container
+                .config
+                .extra_hosts
+                .iter()
+                .map(|(hostname, ip)| rpc::ContainerExtraHostGetAll_Reply_hosts {
+                    hostname: hostname.clone(),
+                    ip: ip.clone(),
+                })
+                .collect()
+        }))
+    }
+
+    /// Get the logs of a container
+    fn container_logs(
+        &self,
+        call: &mut dyn rpc::Call_ContainerLogs,
+        follow: bool,
+        tail: Option<i64>,
+        since: Option<i64>,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        // Following the logs requires more
+        if follow && !call.wants_more() {
+            call.reply_requires_more()?;
+            return Ok(());
+        }
+
+        // Get the container ID. This is scoped so that we don't hold the state lock while
+        // streaming logs.
+        let container_id = {
+            let state = self.state.read().unwrap();
+
+            // Get the config for the requested container
+            let container = match &container_name {
+                Some(container_name) => state.named_containers.get(container_name),
+                None => state.default_container.as_ref(),
+            };
+
+            match container.and_then(|c| c.id.clone()) {
+                Some(id) => id,
+                None => {
+                    return call.reply_error(format!(
+                        r#"Container "{}" has not been started"#,
+                        container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME)
+                    ))
+                }
+            }
+        };
+
+        // Get the log stream
+        let receiver = handle_err!(
+            self.runtime.container_logs(
+                &container_id,
+                &ContainerLogOptions {
+                    follow,
+                    tail: tail.map(|x| x.max(0) as u64),
+                    since,
+                    stdout: true,
+                    stderr: true,
+                },
+            ),
+            call
+        );
+
+        // Helper to convert log lines to the RPC type
+        let to_rpc = |line: ContainerLogLine| rpc::ContainerLogs_Reply_lines {
+            text: line.text,
+            stderr: line.stderr,
+        };
+
+        if follow {
+            call.set_continues(true);
+
+            // Reply with every batch of lines as they come in
+            while let Ok(line) = receiver.recv() {
+                let mut lines = vec![to_rpc(line)];
+                lines.extend(receiver.try_iter().map(to_rpc));
+                call.reply(lines)?;
+            }
+
+            // Reply one last time to end the stream
+            call.set_continues(false);
+            call.reply(vec![])
+        } else {
+            // Reply with all of the lines
+            call.reply(receiver.iter().map(to_rpc).collect())
+        }
+    }
+
+    /// Get a snapshot of the resource usage of one or all of the containers
+    fn container_stats(
+        &self,
+        call: &mut dyn rpc::Call_ContainerStats,
+        all: bool,
+        container_name: Option<String>,
+    ) -> varlink::Result<()> {
+        // Get the IDs of the containers. This is scoped so that we don't hold the state lock while
+        // getting the stats.
+        let containers: Vec<(Option<String>, String)> = {
+            let state = self.state.read().unwrap();
+
+            if all {
+                state
+                    .default_container
+                    .iter()
+                    .map(|container| (None, container))
+                    .chain(
+                        state
+                            .named_containers
+                            .iter()
+                            .map(|(name, container)| (Some(name.clone()), container)),
+                    )
+                    .filter_map(|(name, container)| container.id.clone().map(|id| (name, id)))
+                    .collect()
+            } else {
+                // Get the config for the requested container
+                let container = match &container_name {
+                    Some(container_name) => state.named_containers.get(container_name),
+                    None => state.default_container.as_ref(),
+                };
+
+                match container.and_then(|c| c.id.clone()) {
+                    Some(id) => vec![(container_name.clone(), id)],
+                    None => {
+                        return call.reply_error(format!(
+                            r#"Container "{}" has not been started"#,
+                            container_name
+                                .as_deref()
+                                .unwrap_or(crate::docker::DEFAULT_CONTAINER_NAME)
+                        ))
+                    }
+                }
+            }
+        };
+
+        let mut stats = Vec::new();
+        for (name, id) in containers {
+            stats.push(handle_err!(
+                tools::container_resource_stats(&*self.runtime, name.as_deref(), &id).context(
+                    format!(
+                        r#"Could not get stats for container "{}""#,
+                        name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME)
+                    )
+                ),
+                call
+            ));
+        }
+        stats.sort_by(|a, b| a.container_name.cmp(&b.container_name));
+
+        call.reply(stats)
+    }
+
+    fn network_create(
+        &self,
+        call: &mut dyn rpc::Call_NetworkCreate,
+        network_name: String,
+        internal: bool,
+        subnet: Option<String>,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Skip networks that have already been created
+        if state.networks.contains_key(&network_name) {
+            return call.reply();
+        }
+
+        let unit_name = handle_err!(
+            std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!"),
+            call
+        );
+        let docker_name = crate::docker::docker_network_name(&unit_name, &network_name);
+        log::debug!("Creating network {}: {}", network_name, docker_name);
+
+        // Label the network so that it can be identified as managed by Lucky
+        let mut labels = HashMap::new();
+        labels.insert(LUCKY_UNIT_LABEL.to_string(), unit_name);
+        labels.insert(LUCKY_NETWORK_LABEL.to_string(), network_name.clone());
+
+        // Create the network
+        let network_id = handle_err!(
+            self.runtime
+                .create_network(&docker_name, &labels, internal, subnet.as_deref()),
+            call
+        );
+        state.networks.insert(network_name, network_id);
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn network_remove(
+        &self,
+        call: &mut dyn rpc::Call_NetworkRemove,
+        network_name: String,
+    ) -> varlink::Result<()> {
+        let mut state = self.state.write().unwrap();
+
+        // Make sure no containers are configured to use the network
+        let using_containers: Vec<&str> = state
+            .named_containers
+            .iter()
+            .map(|(name, c)| (name.as_str(), c))
+            .chain(
+                state
+                    .default_container
+                    .iter()
+                    .map(|c| (DEFAULT_CONTAINER_NAME, c)),
+            )
+            .filter(|(_, c)| c.config.networks.contains_key(&network_name))
+            .map(|(name, _)| name)
+            .collect();
+        if !using_containers.is_empty() {
+            return call.reply_error(format!(
+                "Network \"{}\" is still used by containers: {}",
+                network_name,
+                using_containers.join(", ")
+            ));
+        }
+
+        // Remove the network, only forgetting it once it has been removed
+        if let Some(network_id) = state.networks.get(&network_name) {
+            log::debug!("Removing network: {}", network_name);
+            handle_err!(self.runtime.remove_network(network_id), call);
+            state.networks.remove(&network_name);
+        }
+
+        // Reply empty
+        call.reply()
+    }
+
+    fn network_list(&self, call: &mut dyn rpc::Call_NetworkList) -> varlink::Result<()> {
+        let state = self.state.read().unwrap();
+
+        let unit_name = handle_err!(
+            std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!"),
+            call
+        );
+
+        call.reply(
+            state
+                .networks
+                .keys()
+                .map(|name| rpc::NetworkList_Reply_networks {
+                    name: name.clone(),
+                    docker_name: crate::docker::docker_network_name(&unit_name, name),
+                })
+                .collect(),
+        )
+    }
 }
 
 impl Drop for LuckyDaemon {
@@ -0,0 +1,372 @@
+//! Applies the containers declared in the `containers` section of the `lucky.yaml`
+
+use anyhow::format_err;
+use handlebars::Handlebars;
+use serde_json::json;
+
+use std::collections::HashSet;
+
+use super::*;
+use crate::docker::{ContainerConfig, PortBinding, ResourceLimits, VolumeSource, VolumeTarget};
+use crate::types::{juju::CharmMetadata, ContainerDefinition, JobContainerDefinition};
+
+/// Render the container definitions in the `lucky.yaml` and apply any changes to the daemon state
+///
+/// Only the values that have changed since the definitions were last applied are updated, so
+/// changes made with the `lucky container` commands will be kept until the declared value
+/// changes. Returns `true` if any containers were changed.
+pub(super) fn apply_container_definitions(
+    daemon: &LuckyDaemon,
+    state: &mut DaemonState,
+) -> anyhow::Result<bool> {
+    let definitions = &daemon.lucky_metadata.containers;
+
+    // Skip charms that don't declare any containers
+    if definitions.is_empty() && state.declared_containers.is_empty() {
+        return Ok(false);
+    }
+
+    // Create the template engine
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    let context = template_context(daemon, state)?;
+
+    // Render the container definitions
+    let mut rendered = HashMap::new();
+    for (name, definition) in definitions {
+        let mut value = serde_json::to_value(definition)?;
+        render_strings(&handlebars, &context, &mut value).context(format!(
+            r#"Could not render definition for container "{}""#,
+            name
+        ))?;
+        rendered.insert(name.clone(), serde_json::from_value(value)?);
+    }
+
+    let mut changed = false;
+
+    // Remove containers that are no longer declared
+    let removed_containers: Vec<String> = state
+        .declared_containers
+        .keys()
+        .filter(|name| !rendered.contains_key(*name))
+        .cloned()
+        .collect();
+    for name in removed_containers {
+        if let Some(container) = get_container(state, &name) {
+            log::debug!(
+                "Removing container no longer declared in lucky.yaml: {}",
+                name
+            );
+            container.update(|c| c.pending_removal = true);
+            changed = true;
+        }
+        state.declared_containers.remove(&name);
+    }
+
+    // Apply the declared containers that have changed
+    for (name, definition) in rendered {
+        let old_definition = state.declared_containers.get(&name).cloned();
+        if old_definition.as_ref() == Some(&definition) {
+            continue;
+        }
+
+        log::debug!("Applying container definition from lucky.yaml: {}", name);
+        apply_definition(state, &name, old_definition.as_ref(), &definition).context(format!(
+            r#"Could not apply definition for container "{}""#,
+            name
+        ))?;
+        state.declared_containers.insert(name, definition);
+        changed = true;
+    }
+
+    Ok(changed)
+}
+
+/// Apply the difference between the old and new definition of a container
+fn apply_definition(
+    state: &mut DaemonState,
+    name: &str,
+    old: Option<&ContainerDefinition>,
+    new: &ContainerDefinition,
+) -> anyhow::Result<()> {
+    // Parse the ports and volumes before changing anything
+    let old_ports = parse_ports(old.map_or(&[][..], |d| d.ports.as_slice()))?;
+    let new_ports = parse_ports(&new.ports)?;
+    let old_volumes = parse_volumes(old.map_or(&[][..], |d| d.volumes.as_slice()))?;
+    let new_volumes = parse_volumes(&new.volumes)?;
+    let limits = container_limits(state, name, old, new)?;
+    let init_containers = if old.map(|d| &d.init) != Some(&new.init) {
+        Some(
+            new.init
+                .iter()
+                .map(job_container_config)
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        )
+    } else {
+        None
+    };
+
+    // Create the container if it doesn't exist
+    if get_container(state, name).is_none() {
+        let container: Cd<ContainerInfo> = ContainerInfo::new(&new.image).into();
+        if name == DEFAULT_CONTAINER_NAME {
+            state.default_container = Some(container);
+        } else {
+            state.named_containers.insert(name.into(), container);
+        }
+    }
+    let container = get_container(state, name).expect("Missing container");
+
+    container.update(|c| {
+        // Set the image
+        if old.map(|d| &d.image) != Some(&new.image) {
+            c.config.image = new.image.clone();
+            c.image_build = None;
+        }
+
+        // Update the environment
+        if let Some(old) = old {
+            for key in old.env.keys().filter(|key| !new.env.contains_key(*key)) {
+                c.config.env_vars.remove(key);
+            }
+        }
+        for (key, value) in &new.env {
+            if old.and_then(|d| d.env.get(key)) != Some(value) {
+                c.config.env_vars.insert(key.clone(), value.clone());
+            }
+        }
+
+        // Update the ports
+        for port in old_ports.difference(&new_ports) {
+            c.config.ports.remove(port);
+            c.exposed_ports.remove(port);
+        }
+        for port in new_ports.difference(&old_ports) {
+            c.config.ports.insert(port.clone());
+        }
+
+        // Update the volumes
+        for target in old_volumes.keys() {
+            if !new_volumes.contains_key(target) {
+                c.config.volumes.remove(target);
+                c.config.mount_options.remove(target);
+            }
+        }
+        for (target, source) in &new_volumes {
+            if old_volumes.get(target) != Some(source) {
+                c.config.volumes.insert(target.clone(), source.clone());
+            }
+        }
+
+        // Set the network
+        if old.map(|d| &d.network) != Some(&new.network) {
+            c.config.network = new.network.clone();
+        }
+
+        // Set the command
+        if old.map(|d| &d.command) != Some(&new.command) {
+            c.config.command = new.command.clone();
+        }
+
+        // Set the limits
+        if let Some(limits) = limits {
+            c.config.limits = limits;
+        }
+
+        // Set the init containers
+        if let Some(init_containers) = init_containers {
+            c.init_containers = init_containers;
+        }
+    });
+
+    // Set whether or not to expose the ports. This only changes which Juju ports are opened, so
+    // the container doesn't need to be re-created.
+    if old.map(|d| d.auto_expose) != Some(new.auto_expose) {
+        container.update_untracked(|c| c.auto_expose = new.auto_expose);
+    }
+
+    Ok(())
+}
+
+/// Get the container's limits with the changed limits in the new definition applied, or `None` if
+/// the limits haven't changed
+fn container_limits(
+    state: &mut DaemonState,
+    name: &str,
+    old: Option<&ContainerDefinition>,
+    new: &ContainerDefinition,
+) -> anyhow::Result<Option<ResourceLimits>> {
+    if old.map(|d| &d.limits) == Some(&new.limits) {
+        return Ok(None);
+    }
+
+    let mut limits = get_container(state, name)
+        .map(|container| container.config.limits.clone())
+        .unwrap_or_default();
+
+    // Remove the limits that are no longer declared
+    if let Some(old) = old {
+        for limit in old
+            .limits
+            .keys()
+            .filter(|key| !new.limits.contains_key(*key))
+        {
+            limits.set(limit, None)?;
+        }
+    }
+
+    // Set the limits that have changed, removing limits that are empty
+    for (limit, value) in &new.limits {
+        if old.and_then(|d| d.limits.get(limit)) != Some(value) {
+            let value = Some(value.as_str()).filter(|value| !value.is_empty());
+            limits
+                .set(limit, value)
+                .context(format!("Could not set container limit {}", limit))?;
+        }
+    }
+
+    Ok(Some(limits))
+}
+
+/// Get the container config for a job container or init container
+pub(super) fn job_container_config(
+    definition: &JobContainerDefinition,
+) -> anyhow::Result<ContainerConfig> {
+    let mut config = ContainerConfig::new(&definition.image);
+    config.env_vars = definition.env.clone();
+    config.volumes = parse_volumes(&definition.volumes)?;
+    config.network = definition.network.clone();
+    config.networks = definition
+        .networks
+        .iter()
+        .map(|network| (network.clone(), HashSet::new()))
+        .collect();
+    config.entrypoint = definition.entrypoint.clone();
+    config.command = definition.command.clone();
+
+    Ok(config)
+}
+
+/// Get a container by its name in the `lucky.yaml`
+fn get_container<'a>(state: &'a mut DaemonState, name: &str) -> Option<&'a mut Cd<ContainerInfo>> {
+    if name == DEFAULT_CONTAINER_NAME {
+        state.default_container.as_mut()
+    } else {
+        state.named_containers.get_mut(name)
+    }
+}
+
+/// Parse port bindings in the form `[host_ip:]host_port[-end]:container_port[-end][/protocol]`
+///
+/// The host port may be `auto` to allocate a free host port when the container is deployed, and
+/// IPv6 host IPs must be put in brackets, such as `[::1]:8080:80`.
+fn parse_ports(ports: &[String]) -> anyhow::Result<HashSet<PortBinding>> {
+    ports
+        .iter()
+        .map(|port| {
+            port.parse()
+                .context(format!("Could not parse port binding: {}", port))
+        })
+        .collect()
+}
+
+/// Parse volumes in the form `source:target`
+fn parse_volumes(volumes: &[String]) -> anyhow::Result<HashMap<VolumeTarget, VolumeSource>> {
+    volumes
+        .iter()
+        .map(|volume| {
+            let mut parts = volume.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(source), Some(target)) if !source.is_empty() && !target.is_empty() => {
+                    Ok((VolumeTarget(target.into()), VolumeSource(source.into())))
+                }
+                _ => Err(format_err!(
+                    "Could not parse volume, expected `source:target`: {}",
+                    volume
+                )),
+            }
+        })
+        .collect()
+}
+
+/// Get the data that `lucky.yaml` templates are rendered with
+pub(super) fn template_context(
+    daemon: &LuckyDaemon,
+    state: &DaemonState,
+) -> anyhow::Result<JsonValue> {
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let config: serde_json::Map<String, JsonValue> = state
+        .charm_config
+        .iter()
+        .map(|(key, value)| (key.clone(), (**value).clone()))
+        .collect();
+    let kv: serde_json::Map<String, JsonValue> = state
+        .kv
+        .iter()
+        .map(|(key, value)| (key.clone(), JsonValue::String((**value).clone())))
+        .collect();
+    let relations = relation_data(daemon).context("Could not get relation data")?;
+
+    Ok(json!({
+        "unit_name": unit_name,
+        "config": config,
+        "kv": kv,
+        "relations": relations,
+    }))
+}
+
+/// Get the data of every related unit, as a list of units for each relation name
+fn relation_data(daemon: &LuckyDaemon) -> anyhow::Result<JsonValue> {
+    let charm_metadata: CharmMetadata = crate::config::load_yaml(&daemon.charm_dir, "metadata")?;
+    let relation_names = charm_metadata
+        .provides
+        .iter()
+        .chain(&charm_metadata.requires)
+        .chain(&charm_metadata.peers)
+        .flat_map(|relations| relations.keys());
+
+    let mut relations = serde_json::Map::new();
+    for relation_name in relation_names {
+        let mut units = Vec::new();
+        for relation_id in juju::relation_ids(relation_name)? {
+            for remote_unit in juju::relation_list(Some(relation_id.clone()))? {
+                let data = juju::relation_get(
+                    Some(juju::SpecificRelation {
+                        relation_id: relation_id.clone(),
+                        remote_unit,
+                    }),
+                    false,
+                )?;
+                units.push(serde_json::to_value(data)?);
+            }
+        }
+        relations.insert(relation_name.clone(), JsonValue::Array(units));
+    }
+
+    Ok(JsonValue::Object(relations))
+}
+
+/// Render every string in a JSON value as a Handlebars template
+pub(super) fn render_strings(
+    handlebars: &Handlebars,
+    context: &JsonValue,
+    value: &mut JsonValue,
+) -> anyhow::Result<()> {
+    match value {
+        JsonValue::String(template) => *template = handlebars.render_template(template, context)?,
+        JsonValue::Array(values) => {
+            for value in values {
+                render_strings(handlebars, context, value)?;
+            }
+        }
+        JsonValue::Object(map) => {
+            for value in map.values_mut() {
+                render_strings(handlebars, context, value)?;
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! Applies the `docker-settings` section of the `lucky.yaml`
+
+use handlebars::Handlebars;
+
+use super::container_definitions::{render_strings, template_context};
+use super::*;
+use crate::docker::runtime::configure_docker_daemon;
+use crate::types::ContainerRuntimeType;
+
+/// Render the Docker daemon settings in the `lucky.yaml` and write them to Docker's `daemon.json`
+///
+/// Settings that were applied previously but have since been removed from the `lucky.yaml` are
+/// removed from the `daemon.json`. This is idempotent: Docker is only restarted if the
+/// `daemon.json` actually changed, and the containers stopping during the restart are not reported
+/// as having died.
+pub(super) fn apply_docker_daemon_config(
+    daemon: &LuckyDaemon,
+    state: &mut DaemonState,
+) -> anyhow::Result<()> {
+    let daemon_config = &daemon.lucky_metadata.docker_settings.daemon_config;
+
+    // Skip charms that don't use Docker or have never declared any Docker daemon settings
+    if !daemon.lucky_metadata.use_docker
+        || (daemon_config == &Default::default() && state.docker_daemon_config_keys.is_empty())
+    {
+        return Ok(());
+    }
+    if daemon.lucky_metadata.container_runtime != ContainerRuntimeType::Docker {
+        log::warn!("Ignoring Docker daemon settings because the container runtime is not Docker");
+        return Ok(());
+    }
+
+    // Render the settings
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    let context = template_context(daemon, state)?;
+    let mut settings = serde_json::to_value(daemon_config)?;
+    render_strings(&handlebars, &context, &mut settings)
+        .context("Could not render Docker daemon settings")?;
+
+    let settings_map = settings
+        .as_object_mut()
+        .context("Docker daemon settings must be a JSON object")?;
+
+    // Remove settings that were rendered empty from the config
+    for value in settings_map.values_mut() {
+        clear_empty(value);
+    }
+
+    // Remove settings that were applied previously but are no longer in the lucky.yaml
+    for key in &state.docker_daemon_config_keys {
+        settings_map.entry(key.clone()).or_insert(JsonValue::Null);
+    }
+    let managed_keys: HashSet<String> = settings_map
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, _)| key.clone())
+        .collect();
+
+    if daemon
+        .event_suppression
+        .suppress_during(|| configure_docker_daemon(&settings))?
+    {
+        log::info!("Restarted Docker to apply daemon config changes");
+    }
+    state.docker_daemon_config_keys = managed_keys;
+
+    Ok(())
+}
+
+/// Drop empty strings from a rendered setting and set the setting to `null` if it is empty
+fn clear_empty(value: &mut JsonValue) {
+    let is_empty_string = |value: &JsonValue| value.as_str() == Some("");
+
+    match value {
+        JsonValue::Array(values) => values.retain(|value| !is_empty_string(value)),
+        JsonValue::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .filter(|(_, value)| !is_empty_string(value))
+                .collect()
+        }
+        _ => (),
+    }
+
+    let is_empty = match value {
+        JsonValue::String(string) => string.is_empty(),
+        JsonValue::Array(values) => values.is_empty(),
+        JsonValue::Object(map) => map.is_empty(),
+        _ => false,
+    };
+    if is_empty {
+        *value = JsonValue::Null;
+    }
+}
@@ -0,0 +1,223 @@
+//! Docker event watcher that reacts to Lucky's containers exiting
+
+use futures::prelude::*;
+use shiplift::{
+    builder::{EventFilter, EventFilterType},
+    rep::Event,
+    EventsOptions,
+};
+
+use std::collections::HashSet;
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use super::*;
+use crate::docker::{runtime::ContainerRuntime, DEFAULT_CONTAINER_NAME, LUCKY_UNIT_LABEL};
+
+/// How long to wait before re-subscribing to the Docker event stream if it is closed
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Spawn a thread that watches the Docker event stream for Lucky's containers exiting
+///
+/// When one of the containers in the daemon state dies, the watcher will use `juju-run` to run the
+/// `lucky daemon container-died` command, which will update the container's status and run any
+/// `container-died` scripts in the charm's `lucky.yaml`. When a container that died is started
+/// again, such as by its restart policy, the watcher runs `lucky daemon container-started` to clear
+/// the container's status. Containers dying while `event_suppression` is active are not reported.
+pub(super) fn spawn_event_watcher(
+    runtime: Arc<dyn ContainerRuntime>,
+    state: Arc<RwLock<DaemonState>>,
+    event_suppression: Arc<EventSuppression>,
+    stop_listening: Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let lucky_exe = std::env::current_exe().context("Could not determine Lucky exe path")?;
+
+    thread::Builder::new()
+        .name("docker-events".into())
+        .spawn(move || {
+            // The IDs of containers that have been killed for running out of memory
+            let mut oom_killed = HashSet::new();
+
+            // Until the daemon is stopped
+            while !stop_listening.load(Ordering::SeqCst) {
+                // Subscribe to the Docker event stream
+                let (sender, receiver) = channel();
+                let events_options = EventsOptions::builder()
+                    .filter(vec![
+                        EventFilter::Type(EventFilterType::Container),
+                        EventFilter::Label(format!("{}={}", LUCKY_UNIT_LABEL, unit_name)),
+                    ])
+                    .build();
+                crate::rt::spawn(
+                    runtime
+                        .connect()
+                        .events(&events_options)
+                        .for_each(move |event| {
+                            sender.send(event).map_err(|_| {
+                                shiplift::Error::InvalidResponse(
+                                    "Event receiver disconnected".into(),
+                                )
+                            })
+                        })
+                        .map_err(|e| log::debug!("Docker event stream closed: {}", e)),
+                );
+
+                // Handle events until the stream is closed
+                loop {
+                    match receiver.recv_timeout(Duration::from_secs(1)) {
+                        Ok(event) => handle_event(
+                            &*runtime,
+                            &state,
+                            &event_suppression,
+                            &event,
+                            &mut oom_killed,
+                            &unit_name,
+                            &lucky_exe,
+                        ),
+                        Err(RecvTimeoutError::Timeout) => {
+                            if stop_listening.load(Ordering::SeqCst) {
+                                return;
+                            }
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                    }
+                }
+
+                // Wait before trying to subscribe again. Docker may not be installed yet.
+                thread::sleep(RESUBSCRIBE_INTERVAL);
+            }
+        })
+        .context("Could not spawn Docker event watcher thread")?;
+
+    Ok(())
+}
+
+/// Handle a Docker event
+fn handle_event(
+    runtime: &dyn ContainerRuntime,
+    state: &Arc<RwLock<DaemonState>>,
+    event_suppression: &EventSuppression,
+    event: &Event,
+    oom_killed: &mut HashSet<String>,
+    unit_name: &str,
+    lucky_exe: &std::path::Path,
+) {
+    // Skip non-container events
+    if event.typ != "container" {
+        return;
+    }
+
+    // Skip containers stopping while Lucky is restarting the container runtime
+    if matches!(event.action.as_str(), "die" | "oom") && event_suppression.is_suppressed() {
+        log::debug!(
+            "Ignoring container {} event during container runtime restart: {}",
+            event.action,
+            event.actor.id
+        );
+        return;
+    }
+
+    // Find the Lucky name of the container. This is scoped so that we don't hold the state lock
+    // while running `juju-run`.
+    let container_name = {
+        let state = state.read().unwrap();
+        let container_id = Some(&event.actor.id);
+
+        if state
+            .default_container
+            .as_ref()
+            .map_or(false, |info| info.id.as_ref() == container_id)
+        {
+            None
+        } else if let Some((name, _)) = state
+            .named_containers
+            .iter()
+            .find(|(_, info)| info.id.as_ref() == container_id)
+        {
+            Some(name.clone())
+        } else {
+            // Skip containers that aren't managed by Lucky
+            return;
+        }
+    };
+    let log_name = container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME);
+
+    match event.action.as_str() {
+        // Remember containers that ran out of memory so we can report it when they die
+        "oom" => {
+            log::warn!(r#"Container "{}" ran out of memory"#, log_name);
+            oom_killed.insert(event.actor.id.clone());
+        }
+        // Report containers that have died
+        "die" => {
+            // Skip containers that have already been restarted, such as when an update is
+            // rolled back
+            if let Ok(Some(details)) = runtime.inspect_container(&event.actor.id) {
+                if details["State"]["Running"].as_bool().unwrap_or(false) {
+                    return;
+                }
+            }
+
+            let exit_code = event
+                .actor
+                .attributes
+                .get("exitCode")
+                .map_or("-1", String::as_str);
+            let was_oom_killed = oom_killed.remove(&event.actor.id);
+            log::warn!(
+                r#"Container "{}" exited with code {}{}"#,
+                log_name,
+                exit_code,
+                if was_oom_killed {
+                    " ( out of memory )"
+                } else {
+                    ""
+                }
+            );
+
+            // Use Juju run to create a Juju context and run `lucky container-died`
+            let mut command = format!(
+                "LUCKY_CONTEXT=daemon {} container-died --exit-code {}",
+                lucky_exe.to_string_lossy(),
+                exit_code
+            );
+            if let Some(name) = &container_name {
+                command.push_str(&format!(" --container '{}'", name));
+            }
+            if was_oom_killed {
+                command.push_str(" --oom-killed");
+            }
+            if let Err(e) = crate::process::run_cmd("juju-run", &[unit_name, &command]) {
+                log::error!("Error running container-died process: {:?}", e);
+            }
+        }
+        // Clear the status of containers that died when they are started again
+        "start" | "restart" => {
+            let has_died_status = state
+                .read()
+                .unwrap()
+                .script_statuses
+                .contains_key(&tools::container_status_id(container_name.as_deref()));
+            if !has_died_status {
+                return;
+            }
+            log::info!(r#"Container "{}" was restarted"#, log_name);
+
+            // Use Juju run to create a Juju context and run `lucky container-started`
+            let mut command = format!(
+                "LUCKY_CONTEXT=daemon {} container-started",
+                lucky_exe.to_string_lossy()
+            );
+            if let Some(name) = &container_name {
+                command.push_str(&format!(" --container '{}'", name));
+            }
+            if let Err(e) = crate::process::run_cmd("juju-run", &[unit_name, &command]) {
+                log::error!("Error running container-started process: {:?}", e);
+            }
+        }
+        _ => (),
+    }
+}
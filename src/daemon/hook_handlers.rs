@@ -3,8 +3,7 @@
 use std::time::Duration;
 
 use super::*;
-use crate::docker::ContainerInfo;
-use crate::rt::block_on;
+use crate::docker::{runtime::ContainerRuntime, ContainerInfo};
 use crate::types::{ScriptState, ScriptStatus};
 
 pub(super) fn handle_pre_hook(daemon: &LuckyDaemon, hook_name: &str) -> anyhow::Result<()> {
@@ -12,6 +11,7 @@ pub(super) fn handle_pre_hook(daemon: &LuckyDaemon, hook_name: &str) -> anyhow::
         "install" => handle_pre_install(daemon),
         "config-changed" => handle_pre_config_changed(daemon),
         "upgrade-charm" => handle_pre_upgrade_charm(daemon),
+        "update-status" => handle_pre_update_status(daemon),
         _ => Ok(()),
     }
 }
@@ -32,10 +32,27 @@ fn handle_pre_install(daemon: &LuckyDaemon) -> anyhow::Result<()> {
 
     // If Docker support is enabled
     if daemon.lucky_metadata.use_docker {
-        daemon_set_status!(&mut state, ScriptState::Maintenance, "Installing docker");
-
-        // Make sure Docker is installed
-        crate::docker::ensure_docker()?;
+        daemon_set_status!(
+            &mut state,
+            ScriptState::Maintenance,
+            format!("Installing {}", daemon.runtime.name())
+        );
+
+        // Make sure the container runtime is installed
+        daemon.runtime.ensure_installed()?;
+
+        // Configure Docker with the settings from the lucky.yaml
+        docker_settings::apply_docker_daemon_config(daemon, &mut state)?;
+
+        // Build any container images declared in the lucky.yaml
+        if !daemon.lucky_metadata.container_images.is_empty() {
+            daemon_set_status!(
+                &mut state,
+                ScriptState::Maintenance,
+                "Building container images"
+            );
+            tools::update_container_images(daemon, &mut state)?;
+        }
 
         daemon_set_status!(&mut state, ScriptState::Active);
     }
@@ -49,31 +66,46 @@ fn handle_pre_config_changed(daemon: &LuckyDaemon) -> anyhow::Result<()> {
     // Update the configuration cache
     update_config_cache(&mut state)?;
 
+    // Update the Docker settings, which may be rendered from the charm config
+    docker_settings::apply_docker_daemon_config(daemon, &mut state)?;
+
     Ok(())
 }
 
 #[function_name::named]
 fn handle_post_stop(daemon: &LuckyDaemon) -> anyhow::Result<()> {
     let mut state = daemon.state.write().unwrap();
-    let docker_conn = daemon.get_docker_conn()?;
-    let docker_conn = docker_conn.lock().unwrap();
 
     daemon_set_status!(&mut state, ScriptState::Maintenance, "Removing containers");
 
-    for mut container_info in state.named_containers.values_mut() {
-        remove_container(&docker_conn, &mut container_info)?;
+    // Remove containers in the reverse of the order that they were started in so that containers
+    // are removed before the containers that they depend on
+    let mut start_order = tools::container_start_order(&state)?;
+    start_order.reverse();
+    for container_name in start_order {
+        let container_info = if let Some(name) = &container_name {
+            state.named_containers.get_mut(name)
+        } else {
+            state.default_container.as_mut()
+        };
+
+        if let Some(container_info) = container_info {
+            remove_container(&*daemon.runtime, container_info)?;
+        }
     }
 
     // Erase container config
     state.named_containers.clear();
+    state.default_container = None;
+    state.declared_containers.clear();
 
-    if let Some(container_info) = &mut state.default_container {
-        remove_container(&docker_conn, container_info)?;
+    // Remove Lucky networks now that the containers have been removed
+    for (network_name, network_id) in state.networks.drain() {
+        if let Err(e) = daemon.runtime.remove_network(&network_id) {
+            log::warn!("Could not remove network \"{}\": {:?}", network_name, e);
+        }
     }
 
-    // Erase container config
-    state.default_container = None;
-
     daemon_set_status!(&mut state, ScriptState::Active);
     Ok(())
 }
@@ -87,6 +119,14 @@ fn handle_pre_upgrade_charm(daemon: &LuckyDaemon) -> anyhow::Result<()> {
         "Updating containers after charm upgrade"
     );
 
+    // Apply any changes to the Docker settings in the upgraded lucky.yaml
+    docker_settings::apply_docker_daemon_config(daemon, &mut state)
+        .context("Could not apply Docker settings during charm upgrade")?;
+
+    // Build new container images and rebuild images with a changed build context
+    tools::update_container_images(daemon, &mut state)
+        .context("Could not update container images during charm upgrade")?;
+
     // Mark any containers as dirty because they need to be restarted
     if let Some(container) = &mut state.default_container {
         container.mark_dirty();
@@ -107,6 +147,31 @@ fn handle_pre_upgrade_charm(daemon: &LuckyDaemon) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn handle_pre_update_status(daemon: &LuckyDaemon) -> anyhow::Result<()> {
+    if !daemon.lucky_metadata.use_docker {
+        return Ok(());
+    }
+
+    // Reconcile the daemon state with the containers that actually exist in Docker. Container
+    // updates must not be applied at the same time, because the containers that are being deployed
+    // aren't recorded in the state yet and would be removed as orphans.
+    let updates_guard = daemon.container_updates_lock.lock().unwrap();
+    let mut state = daemon.state.write().unwrap();
+    let changed = tools::reconcile_containers(&*daemon.runtime, &mut state)
+        .context("Could not reconcile daemon state with Docker containers")?;
+
+    // Drop state and the updates lock while we apply container updates
+    drop(state);
+    drop(updates_guard);
+
+    // Re-create any containers that were missing
+    if changed {
+        tools::apply_container_updates(daemon).context("Could not re-create missing containers")?;
+    }
+
+    Ok(())
+}
+
 //
 // Helpers
 //
@@ -137,20 +202,18 @@ fn update_config_cache(state: &mut DaemonState) -> anyhow::Result<()> {
 
 /// Helper to remove a given container
 fn remove_container(
-    docker_conn: &shiplift::Docker,
+    runtime: &dyn ContainerRuntime,
     container_info: &mut Cd<ContainerInfo>,
 ) -> anyhow::Result<()> {
     // If container has an ID
     if let Some(id) = &container_info.id {
-        let container = docker_conn.containers().get(id);
-
         // Stop the container
         log::debug!("Stopping container: {}", id);
-        block_on(container.stop(Some(Duration::from_secs(10))))?;
+        runtime.stop_container(id, Duration::from_secs(10))?;
 
         // Remove the container
         log::debug!("Removing container: {}", id);
-        block_on(container.delete())?;
+        runtime.remove_container(id, false)?;
 
         // Unset the container id
         container_info.update(|info| info.id = None);
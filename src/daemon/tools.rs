@@ -1,22 +1,43 @@
-use anyhow::format_err;
-use futures::prelude::*;
-use rand::{seq::IteratorRandom, thread_rng};
-use shiplift::{builder::ExecContainerOptions, PullOptions};
+use anyhow::{bail, format_err};
+use chrono::Utc;
+use get_port::PortRange;
+use rand::{seq::IteratorRandom, thread_rng, Rng};
+use shiplift::tty::StreamType;
 use subprocess::{Exec, ExitStatus, Redirection};
 
+use std::collections::{BTreeMap, HashSet};
 use std::env;
+use std::fs;
 use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, UdpSocket};
+use std::path::{Component, Path};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-
-use crate::docker::ContainerInfo;
-use crate::rt::block_on;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::docker::runtime::{ContainerLogOptions, ContainerRuntime};
+use crate::docker::{
+    ContainerConfig, ContainerInfo, ImageBuild, ProxySettings, PullPolicy, RegistryCredentials,
+    TrackedImage, UpdateStrategy, DEFAULT_CONTAINER_NAME, LUCKY_CONTAINER_LABEL, LUCKY_JOB_LABEL,
+    LUCKY_UNIT_LABEL,
+};
 use crate::types::{
-    CharmScript, CharmScriptType, ScriptState, ScriptStatus, LUCKY_EXIT_CODE_HELPER_PREFIX,
+    CharmScript, CharmScriptType, JobContainerDefinition, ScriptState, ScriptStatus,
 };
 
 const CONTAINER_SUFFIX_CHARS: &str = "abcdefghijklmnopqrstuvwxyz0123456789";
 
+/// How long to collect container log lines for before forwarding them to the Juju log
+const LOG_FORWARD_INTERVAL: Duration = Duration::from_secs(5);
+/// The maximum number of container log lines to forward to the Juju log at once
+const LOG_FORWARD_MAX_LINES: usize = 100;
+/// How long to wait, in seconds, for a dependency to become healthy if it doesn't have a health
+/// check timeout
+const DEFAULT_DEPENDENCY_TIMEOUT: u64 = 60;
+/// How long, in seconds, a new container without a health check timeout must keep running after it
+/// is started for an update to be considered successful if it doesn't have a startup grace period
+const DEFAULT_STARTUP_GRACE_PERIOD: u64 = 3;
+
 use super::*;
 
 /// Load the daemon state from the filesystem
@@ -224,6 +245,14 @@ pub(super) fn run_charm_script(
             &environment,
             script_id_override,
         ),
+        // Run job container
+        CharmScriptType::JobContainer { job_container } => run_job_container_script(
+            daemon,
+            job_container,
+            hook_name,
+            &environment,
+            script_id_override,
+        ),
     }
 }
 
@@ -443,12 +472,6 @@ fn run_container_script(
         };
     }
 
-    // Get the docker connection
-    let docker_conn = daemon.get_docker_conn()?;
-    let docker_conn = docker_conn.lock().unwrap();
-    let containers = docker_conn.containers();
-    let container = containers.get(&container_id);
-
     // The command environment
     let mut env: Vec<String> = environment
         .iter()
@@ -460,32 +483,20 @@ fn run_container_script(
         "LUCKY_SCRIPT_ID={}",
         script_id_override.unwrap_or(&script_name.as_str())
     ));
-    // TODO: https://github.com/softprops/shiplift/issues/219
-    // We currently set the context to "daemon" so we can call `lucky exit-code-helper` to help
-    // us get the exit code of the container script.
-    env.push("LUCKY_CONTEXT=daemon".into());
 
     // Build the command
     let mut cmd: Vec<String>;
     match script_type {
-        ScriptType::Inline { content, mut shell } => {
-            // Set command to the lucky exit code helper ( see comment above )
-            cmd = vec!["lucky".into(), "exit-code-helper".into()];
-
+        ScriptType::Inline { content, shell } => {
             // Add shell command
-            cmd.extend(shell.drain(0..));
+            cmd = shell;
 
             // Add inline script as last arg
             cmd.push(content);
         }
         ScriptType::Named { name, mut args } => {
-            // Set command to the lucky exit code helper ( see comment above )
-            cmd = vec![
-                "lucky".into(),
-                "exit-code-helper".into(),
-                // Add container script
-                format!("/lucky/container_scripts/{}", name),
-            ];
+            // Add container script
+            cmd = vec![format!("/lucky/container_scripts/{}", name)];
 
             // Add script args
             cmd.extend(args.drain(0..));
@@ -498,77 +509,540 @@ fn run_container_script(
         cmd
     );
 
-    // Build exec options
-    let exec_options = ExecContainerOptions::builder()
-        .attach_stderr(true)
-        .attach_stdout(true)
-        .env(env.iter().map(AsRef::as_ref).collect())
-        .cmd(cmd.iter().map(AsRef::as_ref).collect())
-        .build();
-
-    // Instantiate exit code
-    let exit_code: Arc<Mutex<Option<i32>>> = Arc::new(Mutex::new(None));
-    let exit_code_ = exit_code.clone();
-
     // Exec script and log output
-    block_on(container.exec(&exec_options).for_each(move |chunk| {
-        let exit_code = &exit_code_;
-        let chunk_str = chunk.as_string_lossy();
-
-        // TODO: https://github.com/softprops/shiplift/issues/219
-        // This hack looks for a special prefix for a line of text that will tell us the exit
-        // code. This output provided by our `lucky daemon exit-code-helper` wrapper command.
-
-        // If the line starts with the exit code indication prefix
-        if chunk_str.starts_with(LUCKY_EXIT_CODE_HELPER_PREFIX) {
-            // Trim output string
-            let chunk_str = chunk_str.trim();
-
-            // Set the exit code
-            *exit_code.lock().unwrap() = Some(
-                chunk_str
-                    .trim_start_matches(LUCKY_EXIT_CODE_HELPER_PREFIX)
-                    .parse()
-                    .map_err(|e| {
-                        shiplift::Error::InvalidResponse(format!(
-                            "Could not parse container script exit code: {}",
-                            e
-                        ))
-                    })?,
-            );
-
-        // If line doesn't start with exit-code prefix
-        } else {
-            // Log the output
-            log::debug!("output: {}", chunk.as_string_lossy());
-        }
-        Ok(())
-    }))
-    .context(format!(
+    let output = exec_in_container(&*daemon.runtime, &container_id, cmd, env).context(format!(
         r#"failed to exec script "{}" for container "{}""#,
         script_name,
         container_name.as_ref().unwrap_or(&"default".into())
     ))?;
 
     // Match exit code and exit accordingly
-    let exit_code = exit_code.lock().unwrap();
-    match *exit_code {
-        Some(0) => Ok(()),
-        Some(code) => Err(format_err!(
+    match output.exit_code {
+        0 => Ok(()),
+        code => Err(format_err!(
             r#"Container script "{}" exited non-zero: {}"#,
             script_name,
             code
         )),
-        None => Err(format_err!(
-            "Error getting exit code from container script: assuming something went wrong."
+    }
+}
+
+/// Run a job container to completion and remove it
+fn run_job_container_script(
+    daemon: &LuckyDaemon,
+    definition: &JobContainerDefinition,
+    hook_name: &str,
+    environment: &HashMap<String, String>,
+    script_id_override: Option<&str>,
+) -> anyhow::Result<()> {
+    let script_name = format!("{}_job", hook_name);
+
+    log::info!("Running job container: {}", script_name);
+
+    // Build the container config, adding the hook environment to the container's environment
+    let mut config = super::container_definitions::job_container_config(definition)?;
+    for (k, v) in environment {
+        config
+            .env_vars
+            .entry(k.clone())
+            .or_insert_with(|| v.clone());
+    }
+    config.env_vars.insert(
+        "LUCKY_SCRIPT_ID".into(),
+        script_id_override.unwrap_or(&script_name).into(),
+    );
+
+    // Get the registry credentials for the image and the proxy settings. This is scoped so that
+    // we don't hold the state lock while the job is running.
+    let (registry_credentials, proxy_settings) = {
+        let state = daemon.state.read().unwrap();
+        (
+            image_registry_credentials(
+                &state,
+                &config.image,
+                definition.registry_auth_config_key.as_deref(),
+            )?,
+            container_proxy_settings(daemon, &state),
+        )
+    };
+
+    // Run the job
+    let exit_code = run_job_container(
+        daemon,
+        &config,
+        &script_name,
+        registry_credentials.as_ref(),
+        proxy_settings.as_ref(),
+    )
+    .context(format!(r#"Could not run job container "{}""#, script_name))?;
+
+    // Match exit code and exit accordingly
+    match exit_code {
+        0 => Ok(()),
+        code => Err(format_err!(
+            r#"Job container "{}" exited non-zero: {}"#,
+            script_name,
+            code
         )),
     }
 }
 
+/// Create a container that is run to completion, log its output, and return its exit code
+///
+/// The container is removed once it has exited. The `job_name` is used to label the container and
+/// is the name that the container can be reached by on its Lucky networks.
+fn run_job_container(
+    daemon: &LuckyDaemon,
+    config: &ContainerConfig,
+    job_name: &str,
+    registry_credentials: Option<&RegistryCredentials>,
+    proxy_settings: Option<&ProxySettings>,
+) -> anyhow::Result<i64> {
+    let runtime = &*daemon.runtime;
+
+    // Pull the image if it isn't present
+    pull_image(
+        runtime,
+        &config.image,
+        PullPolicy::IfNotPresent,
+        registry_credentials,
+    )
+    .context(format!("Could not pull image {}", config.image))?;
+
+    // Create the container
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let mut docker_options = config.to_container_options(
+        &daemon.charm_dir,
+        &daemon.lucky_data_dir,
+        &daemon.socket_path,
+        &unit_name,
+        Some(job_name),
+        proxy_settings,
+    )?;
+    docker_options.name = Some(docker_container_name(&unit_name));
+    // Job containers run to completion so they must not be restarted when they exit
+    docker_options.body["HostConfig"]["RestartPolicy"] = serde_json::json!({ "Name": "no" });
+    // Label the container as a job container so that it isn't mistaken for one of the unit's
+    // long-running containers
+    if let Some(labels) = docker_options.body["Labels"].as_object_mut() {
+        labels.remove(LUCKY_CONTAINER_LABEL);
+        labels.insert(LUCKY_JOB_LABEL.into(), job_name.into());
+    }
+
+    log::trace!("Creating job container with options: {:#?}", docker_options);
+    let container_id = runtime
+        .create_container(&docker_options)
+        .context("Could not create job container")?;
+
+    // Run the container and remove it, even if it failed
+    let result = wait_for_job_container(runtime, &container_id);
+    log::debug!("Removing container: {}", container_id);
+    if let Err(e) = runtime.remove_container(&container_id, true) {
+        log::warn!("{:?}", e.context("Could not remove job container"));
+    }
+
+    result
+}
+
+/// Start a job container and log its output until it exits, returning its exit code
+fn wait_for_job_container(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+) -> anyhow::Result<i64> {
+    log::debug!("Starting job container: {}", container_id);
+    runtime
+        .start_container(container_id)
+        .context("Could not start job container")?;
+
+    // Log the output of the container. The log stream is closed when the container exits.
+    let receiver = runtime.container_logs(
+        container_id,
+        &ContainerLogOptions {
+            follow: true,
+            tail: None,
+            since: None,
+            stdout: true,
+            stderr: true,
+        },
+    )?;
+    for line in receiver {
+        log::info!("output: {}", line.text);
+    }
+
+    runtime.wait_container(container_id)
+}
+
+/// The output of a command run in a container
+pub(super) struct ExecOutput {
+    /// The exit code of the command
+    pub exit_code: i64,
+    /// The output written to stdout
+    pub stdout: String,
+    /// The output written to stderr
+    pub stderr: String,
+}
+
+/// Run a command in a container and collect its output
+///
+/// The output of the command will also be logged at the debug level as it is received.
+pub(super) fn exec_in_container(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    cmd: Vec<String>,
+    env: Vec<String>,
+) -> anyhow::Result<ExecOutput> {
+    // Collect the command output
+    let output = Arc::new(Mutex::new((Vec::new(), Vec::new())));
+    let output_ = output.clone();
+
+    // Exec command and log output
+    let exit_code = runtime.exec(
+        container_id,
+        &cmd,
+        &env,
+        Box::new(move |stream_type, chunk| {
+            log::debug!("output: {}", String::from_utf8_lossy(chunk));
+
+            let mut output = output_.lock().unwrap();
+            if let StreamType::StdErr = stream_type {
+                output.1.extend_from_slice(chunk);
+            } else {
+                output.0.extend_from_slice(chunk);
+            }
+        }),
+    )?;
+    let (stdout, stderr) = output.lock().unwrap().clone();
+
+    Ok(ExecOutput {
+        exit_code,
+        stdout: String::from_utf8_lossy(&stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&stderr).into_owned(),
+    })
+}
+
+/// Get the ID of a container that has been started
+pub(super) fn get_container_id(
+    state: &DaemonState,
+    container_name: Option<&str>,
+) -> anyhow::Result<String> {
+    let container = match container_name {
+        Some(name) => state.named_containers.get(name),
+        None => state.default_container.as_ref(),
+    };
+
+    container.and_then(|c| c.id.clone()).ok_or_else(|| {
+        format_err!(
+            r#"Container "{}" does not exist or has not been started"#,
+            container_name.unwrap_or(DEFAULT_CONTAINER_NAME)
+        )
+    })
+}
+
+/// Copy a file or directory on the host into a container
+///
+/// The `target` is the absolute path that the file or directory will have in the container. File
+/// modes are preserved.
+pub(super) fn copy_to_container(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    source: &Path,
+    target: &str,
+) -> anyhow::Result<()> {
+    let target_path = Path::new(target);
+    let (target_dir, target_name) = match (target_path.parent(), target_path.file_name()) {
+        (Some(dir), Some(name)) if target_path.is_absolute() => (dir, name),
+        _ => bail!(
+            "Container path must be an absolute path to a file or directory: {}",
+            target
+        ),
+    };
+    let metadata =
+        fs::metadata(source).context(format!("Could not read source path: {:?}", source))?;
+
+    // Build an archive containing the source, named after the target
+    let mut archive = tar::Builder::new(Vec::new());
+    if metadata.is_dir() {
+        archive.append_dir_all(target_name, source)?;
+    } else {
+        archive.append_path_with_name(source, target_name)?;
+    }
+    let archive = archive
+        .into_inner()
+        .context("Could not create archive to copy into the container")?;
+
+    // Extract the archive in the target's parent dir
+    log::debug!("Copying {:?} to container path: {}", source, target);
+    runtime.put_archive(container_id, &target_dir.to_string_lossy(), archive)
+}
+
+/// Copy a file or directory in a container to the host
+///
+/// The `target` is the path that the file or directory will have on the host. File modes are
+/// preserved.
+pub(super) fn copy_from_container(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    source: &str,
+    target: &Path,
+) -> anyhow::Result<()> {
+    log::debug!("Copying container path {} to {:?}", source, target);
+    let archive = runtime.get_archive(container_id, source)?;
+    let mut archive = tar::Archive::new(archive.as_slice());
+    archive.set_preserve_permissions(true);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        // Replace the first component of the entry path, which is the name of the source, with
+        // the target
+        let entry_path = entry.path()?.into_owned();
+        let mut components = entry_path.components();
+        components.next();
+        if components
+            .clone()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            bail!("Invalid path in container archive: {:?}", entry_path);
+        }
+        let relative_path = components.as_path();
+        let destination = if relative_path.as_os_str().is_empty() {
+            target.to_path_buf()
+        } else {
+            target.join(relative_path)
+        };
+
+        // Unpack the entry
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent).context(format!("Could not create dir: {:?}", parent))?;
+        }
+        entry
+            .unpack(&destination)
+            .context(format!("Could not write file: {:?}", destination))?;
+    }
+
+    Ok(())
+}
+
+/// Build an image from the charm's `container_images/<image_name>` dir and set it as the image for
+/// the given container, creating the container if it doesn't exist. Returns the built image ID.
+pub(super) fn build_container_image(
+    daemon: &LuckyDaemon,
+    state: &mut DaemonState,
+    image_name: &str,
+    tag: Option<&str>,
+    container_name: Option<&str>,
+) -> anyhow::Result<String> {
+    let image_build = build_charm_image(daemon, image_name, tag)?;
+    let image_id = image_build.image_id.clone();
+    set_container_image_build(state, container_name, image_build);
+
+    Ok(image_id)
+}
+
+/// Build an image from the charm's `container_images/<image_name>` dir
+///
+/// This doesn't need the daemon state so that the state doesn't have to be locked while the image
+/// is built, which can take a long time.
+pub(super) fn build_charm_image(
+    daemon: &LuckyDaemon,
+    image_name: &str,
+    tag: Option<&str>,
+) -> anyhow::Result<ImageBuild> {
+    // Make sure the build context exists
+    let context_dir = daemon.charm_dir.join("container_images").join(image_name);
+    if !context_dir.join("Dockerfile").is_file() {
+        bail!(
+            "Could not find Dockerfile for image \"{}\": {:?} does not exist",
+            image_name,
+            context_dir.join("Dockerfile")
+        );
+    }
+
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let tag = tag.map_or_else(
+        || crate::docker::default_image_tag(&unit_name, image_name),
+        ToOwned::to_owned,
+    );
+    let context_hash = crate::docker::hash_build_context(&context_dir)?;
+
+    // Build the image
+    log::info!("Building container image \"{}\" as {}", image_name, tag);
+    let image_id = daemon
+        .runtime
+        .build_image(&context_dir, &tag)
+        .context(format!(
+            "Could not build container image \"{}\"",
+            image_name
+        ))?;
+    log::debug!("Built container image \"{}\": {}", image_name, image_id);
+
+    Ok(ImageBuild {
+        name: image_name.into(),
+        tag,
+        context_hash,
+        image_id,
+    })
+}
+
+/// Set a built image as the image for the given container, creating the container if it doesn't
+/// exist
+pub(super) fn set_container_image_build(
+    state: &mut DaemonState,
+    container_name: Option<&str>,
+    image_build: ImageBuild,
+) {
+    let tag = image_build.tag.clone();
+    let container = match container_name {
+        Some(name) => state
+            .named_containers
+            .entry(name.into())
+            .or_insert_with(|| ContainerInfo::new(&tag).into()),
+        None => state
+            .default_container
+            .get_or_insert_with(|| ContainerInfo::new(&tag).into()),
+    };
+    container.update(|c| {
+        c.config.image = tag;
+        c.pull_policy = PullPolicy::Never;
+        c.image_digest = None;
+        c.image_build = Some(image_build);
+    });
+}
+
+/// Build any images declared in the `lucky.yaml` that haven't been built yet and rebuild any built
+/// images whose build context has changed
+pub(super) fn update_container_images(
+    daemon: &LuckyDaemon,
+    state: &mut DaemonState,
+) -> anyhow::Result<()> {
+    // Collect the images that need to be built as ( image name, tag, container name )
+    let mut builds: Vec<(String, Option<String>, Option<String>)> = Vec::new();
+
+    // Add declared images that have not been built for their container
+    for (image_name, build_config) in &daemon.lucky_metadata.container_images {
+        let container = match &build_config.container {
+            Some(name) => state.named_containers.get(name),
+            None => state.default_container.as_ref(),
+        };
+        let is_built = container
+            .and_then(|c| c.image_build.as_ref())
+            .map_or(false, |build| &build.name == image_name);
+
+        if !is_built {
+            builds.push((
+                image_name.clone(),
+                build_config.tag.clone(),
+                build_config.container.clone(),
+            ));
+        }
+    }
+
+    // Add built images with a changed build context
+    let built_containers = state
+        .named_containers
+        .iter()
+        .map(|(name, c)| (Some(name), c))
+        .chain(state.default_container.iter().map(|c| (None, c)));
+    for (container_name, container) in built_containers {
+        if let Some(build) = &container.image_build {
+            let context_dir = daemon.charm_dir.join("container_images").join(&build.name);
+            let context_hash = crate::docker::hash_build_context(&context_dir)?;
+
+            if context_hash != build.context_hash {
+                log::debug!("Build context changed for image \"{}\"", build.name);
+                builds.push((
+                    build.name.clone(),
+                    Some(build.tag.clone()),
+                    container_name.cloned(),
+                ));
+            }
+        }
+    }
+
+    // Build the images
+    for (image_name, tag, container_name) in builds {
+        build_container_image(
+            daemon,
+            state,
+            &image_name,
+            tag.as_deref(),
+            container_name.as_deref(),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Update the cached proxy settings from a hook environment and apply them if they changed
+///
+/// The container runtime's proxy settings are updated and, if the charm forwards the proxy
+/// settings to its containers, the containers are re-created with the new settings.
+pub(super) fn update_proxy_settings(
+    daemon: &LuckyDaemon,
+    environment: &HashMap<String, String>,
+) -> anyhow::Result<()> {
+    let mut state = daemon.state.write().unwrap();
+
+    // Skip if the proxy settings haven't changed
+    let proxy_settings = ProxySettings::from_env(environment);
+    if state.proxy_settings == proxy_settings {
+        return Ok(());
+    }
+    log::debug!("Juju proxy settings changed: {:?}", proxy_settings);
+
+    // The new proxy settings are only stored once they have been applied so that they are applied
+    // again by the next hook if this fails
+    if daemon.lucky_metadata.use_docker {
+        // Restarting the runtime stops the containers, which must not be reported as having died
+        daemon
+            .event_suppression
+            .suppress_during(|| daemon.runtime.configure_proxy(&proxy_settings))
+            .context("Could not update container runtime proxy settings")?;
+
+        if daemon.lucky_metadata.forward_proxy_settings
+            && (state.default_container.is_some() || !state.named_containers.is_empty())
+        {
+            if let Some(container) = &mut state.default_container {
+                container.mark_dirty();
+            }
+            for container in state.named_containers.values_mut() {
+                container.mark_dirty();
+            }
+
+            // Re-create the containers with the new settings, which are read from the state, and
+            // restore the previous settings if that fails
+            let previous_proxy_settings =
+                std::mem::replace(&mut state.proxy_settings, proxy_settings);
+            drop(state);
+            if let Err(e) = apply_container_updates(daemon) {
+                daemon.state.write().unwrap().proxy_settings = previous_proxy_settings;
+                return Err(e.context("Could not apply proxy settings to the containers"));
+            }
+
+            return Ok(());
+        }
+    }
+    state.proxy_settings = proxy_settings;
+
+    Ok(())
+}
+
+/// Get the proxy settings to add to the environment of the charm's containers, if any
+fn container_proxy_settings(daemon: &LuckyDaemon, state: &DaemonState) -> Option<ProxySettings> {
+    if daemon.lucky_metadata.forward_proxy_settings {
+        Some(state.proxy_settings.clone())
+    } else {
+        None
+    }
+}
+
 #[function_name::named]
 /// Apply any updates to container configuration for the charm by running
 pub(super) fn apply_container_updates(daemon: &LuckyDaemon) -> anyhow::Result<()> {
     log::debug!("Applying container configuration");
+    let _updates_guard = daemon.container_updates_lock.lock().unwrap();
     let mut state = daemon.state.write().unwrap();
     daemon_set_status!(
         &mut state,
@@ -576,110 +1050,1489 @@ pub(super) fn apply_container_updates(daemon: &LuckyDaemon) -> anyhow::Result<()
         "Applying Docker configuration updates"
     );
 
-    // Apply changes for any updated named containers
-    for mut container in state.named_containers.values_mut() {
-        apply_updates(daemon, &mut container)?;
+    // Restart the containers that depend on containers that will be re-created
+    mark_dependents_dirty(&mut state);
+
+    // Collect the containers that will be re-created so that we can clear any exit statuses
+    let mut updated_containers: Vec<Option<String>> = state
+        .named_containers
+        .iter()
+        .filter(|(_, container)| !container.is_clean())
+        .map(|(name, _)| Some(name.clone()))
+        .collect();
+    if state
+        .default_container
+        .as_ref()
+        .map_or(false, |container| !container.is_clean())
+    {
+        updated_containers.push(None);
     }
 
-    // Remove named containers that are pending removal
+    // Get the registry credentials for the containers that will be re-created
+    let mut registry_credentials = HashMap::new();
+    for container_name in &updated_containers {
+        let container = match container_name {
+            Some(name) => state.named_containers.get(name),
+            None => state.default_container.as_ref(),
+        }
+        .expect("Missing updated container");
+
+        if let Some(credentials) = get_registry_credentials(&state, container)? {
+            registry_credentials.insert(container_name.clone(), credentials);
+        }
+    }
+
+    let proxy_settings = container_proxy_settings(daemon, &state);
+
+    // Allocate host ports for the containers that will be re-created
+    allocate_host_ports(&mut state).context("Could not allocate container host ports")?;
+
+    // Apply changes to the containers, starting containers after their dependencies
+    let mut rolled_back_containers = Vec::new();
+    for container_name in container_start_order(&state)? {
+        let container = match &container_name {
+            Some(name) => state.named_containers.get(name),
+            None => state.default_container.as_ref(),
+        };
+        // Skip containers that were removed while the state lock was released and containers that
+        // are already up-to-date
+        let mut container = match container {
+            Some(container) if !container.is_clean() => container.clone(),
+            _ => continue,
+        };
+        let dependencies = dependencies_to_wait_for(&state, container_name.as_deref());
+
+        // Detach the Docker container from the state while it is being replaced so that the old
+        // container being stopped isn't reported as the container dying
+        get_container_mut(&mut state, container_name.as_deref())
+            .expect("Missing container")
+            .update_untracked(|info| info.id = None);
+        let mut applied_info = ContainerInfo::clone(&container);
+        applied_info.id = None;
+
+        // Release the state lock while waiting for dependencies and deploying the container so that
+        // the daemon can handle other requests, such as `lucky client` calls from init containers,
+        // while images are pulled and containers are started
+        drop(state);
+        let result =
+            wait_for_dependencies(&*daemon.runtime, container_name.as_deref(), &dependencies)
+                .and_then(|()| {
+                    apply_updates(
+                        daemon,
+                        container_name.as_deref(),
+                        &mut container,
+                        registry_credentials.get(&container_name),
+                        proxy_settings.as_ref(),
+                    )
+                });
+        state = daemon.state.write().unwrap();
+        store_applied_container(
+            &mut state,
+            container_name.as_deref(),
+            &applied_info,
+            container,
+        );
+
+        if let UpdateOutcome::RolledBack(e) = result? {
+            rolled_back_containers.push((container_name, e));
+        }
+    }
+
+    // Remove containers that are pending removal
     state
         .named_containers
         .retain(|_name, container| !container.pending_removal);
+    if state
+        .default_container
+        .as_ref()
+        .map_or(false, |container| container.pending_removal)
+    {
+        state.default_container = None;
+    }
 
-    // Apply changes for the default container
-    if let Some(container) = &mut state.default_container {
-        apply_updates(daemon, container)?;
+    // Clear the exit statuses of containers that have been re-created
+    for container_name in &updated_containers {
+        state
+            .script_statuses
+            .remove(&container_status_id(container_name.as_deref()));
+    }
 
-        // Remove container if pending removal
-        if container.pending_removal {
-            state.default_container = None;
+    // Open and close the Juju ports for the exposed container ports
+    reconcile_exposed_ports(&mut state).context("Could not update exposed container ports")?;
+
+    // The images used by the containers only change when containers are re-created
+    if !updated_containers.is_empty() {
+        // Record the images that the containers are running
+        track_container_images(&*daemon.runtime, &mut state)
+            .context("Could not record container images")?;
+
+        // Remove images that are no longer used if all of the containers were updated successfully
+        let image_cleanup = &daemon.lucky_metadata.image_cleanup;
+        if image_cleanup.enabled && rolled_back_containers.is_empty() {
+            match images_to_prune(&*daemon.runtime, &state, image_cleanup.keep_last) {
+                Ok(images) => {
+                    // Release the state lock while the images are removed
+                    drop(state);
+                    let pruned = prune_images(&*daemon.runtime, images);
+                    state = daemon.state.write().unwrap();
+
+                    untrack_images(&mut state, &pruned.untracked);
+                    if let Some(e) = pruned.error {
+                        log::warn!("Could not remove unused images: {:?}", e);
+                    }
+                }
+                Err(e) => log::warn!("Could not get unused images: {:?}", e),
+            }
         }
     }
 
+    // Report the containers that failed to update and were rolled back
+    for (container_name, error) in rolled_back_containers {
+        tools::set_script_status(
+            &mut state,
+            &container_status_id(container_name.as_deref()),
+            ScriptStatus {
+                state: ScriptState::Blocked,
+                message: Some(format!(
+                    r#"Container "{}" update failed and was rolled back: {}"#,
+                    container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME),
+                    error
+                )),
+            },
+        )?;
+    }
+
     daemon_set_status!(&mut state, ScriptState::Active);
     Ok(())
 }
 
+/// Get the images that are used by the unit's containers
+///
+/// This includes the images that the containers are running and the images of their init
+/// containers.
+fn used_images(
+    runtime: &dyn ContainerRuntime,
+    state: &DaemonState,
+) -> anyhow::Result<Vec<TrackedImage>> {
+    let mut images = Vec::new();
+    for container in state
+        .default_container
+        .iter()
+        .chain(state.named_containers.values())
+    {
+        // Get the image that the container is running
+        if let Some(container_id) = &container.id {
+            if let Some(details) = runtime.inspect_container(container_id)? {
+                if let Some(image_id) = details["Image"].as_str() {
+                    images.push(TrackedImage {
+                        image: details["Config"]["Image"]
+                            .as_str()
+                            .unwrap_or(image_id)
+                            .into(),
+                        id: image_id.into(),
+                    });
+                }
+            }
+        }
+
+        // Get the images of the init containers
+        for init_config in &container.init_containers {
+            if let Some(details) = runtime.inspect_image(&init_config.image)? {
+                if let Some(image_id) = details["Id"].as_str() {
+                    images.push(TrackedImage {
+                        image: init_config.image.clone(),
+                        id: image_id.into(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(images)
+}
+
+/// Record the images that are used by the unit's containers as the most recently used images
+pub(super) fn track_container_images(
+    runtime: &dyn ContainerRuntime,
+    state: &mut DaemonState,
+) -> anyhow::Result<()> {
+    for image in used_images(runtime, state)? {
+        state.images.retain(|tracked| tracked.id != image.id);
+        state.images.push(image);
+    }
+
+    Ok(())
+}
+
+/// Get the tracked images that are no longer used by any of the unit's containers and should be
+/// removed
+///
+/// The `keep_last` most recently used of the unused images are kept so that containers can be
+/// rolled back to them.
+pub(super) fn images_to_prune(
+    runtime: &dyn ContainerRuntime,
+    state: &DaemonState,
+    keep_last: usize,
+) -> anyhow::Result<Vec<TrackedImage>> {
+    let used_image_ids: HashSet<String> = used_images(runtime, state)?
+        .into_iter()
+        .map(|image| image.id)
+        .collect();
+
+    // Get the unused images, from least to most recently used
+    let unused_images: Vec<TrackedImage> = state
+        .images
+        .iter()
+        .filter(|image| !used_image_ids.contains(&image.id))
+        .cloned()
+        .collect();
+    let prune_count = unused_images.len().saturating_sub(keep_last);
+
+    Ok(unused_images.into_iter().take(prune_count).collect())
+}
+
+/// The result of removing unused images
+pub(super) struct PrunedImages {
+    /// The images that were removed
+    pub removed: Vec<TrackedImage>,
+    /// The images that should no longer be tracked. This includes the removed images and the images
+    /// that are still used outside of this unit.
+    pub untracked: Vec<TrackedImage>,
+    /// The error that stopped the rest of the images from being removed, if any
+    pub error: Option<anyhow::Error>,
+}
+
+/// Remove the images returned by `images_to_prune`
+///
+/// This doesn't need the daemon state so that the state doesn't have to be locked while the images
+/// are removed. The `untracked` images should be removed from the state with `untrack_images`
+/// afterwards.
+pub(super) fn prune_images(
+    runtime: &dyn ContainerRuntime,
+    images: Vec<TrackedImage>,
+) -> PrunedImages {
+    let mut pruned = PrunedImages {
+        removed: Vec::new(),
+        untracked: Vec::new(),
+        error: None,
+    };
+    for image in images {
+        log::info!("Removing unused image: {} ( {} )", image.image, image.id);
+        match remove_tracked_image(runtime, &image) {
+            Ok(true) => pruned.removed.push(image.clone()),
+            Ok(false) => {
+                // The image is still used outside of this unit, such as by another unit's
+                // container, so stop tracking it without removing it
+                log::debug!("Not removing image {} because it is in use", image.image);
+            }
+            Err(e) => {
+                pruned.error = Some(e.context(format!("Could not remove image {}", image.image)));
+                break;
+            }
+        }
+        pruned.untracked.push(image);
+    }
+
+    pruned
+}
+
+/// Stop tracking the given images
+pub(super) fn untrack_images(state: &mut DaemonState, images: &[TrackedImage]) {
+    state
+        .images
+        .retain(|tracked| !images.iter().any(|image| image.id == tracked.id));
+}
+
+/// Remove a tracked image, returning `false` if the image is in use and could not be removed
+///
+/// The image's tag is removed before the image itself, because images with multiple tags can't be
+/// removed by ID. The tag is only removed if it still points to the tracked image.
+fn remove_tracked_image(
+    runtime: &dyn ContainerRuntime,
+    image: &TrackedImage,
+) -> anyhow::Result<bool> {
+    let tag_matches = runtime
+        .inspect_image(&image.image)?
+        .map_or(false, |details| {
+            details["Id"].as_str() == Some(image.id.as_str())
+        });
+    if tag_matches && !runtime.remove_image(&image.image)? {
+        return Ok(false);
+    }
+
+    // Remove the image by ID in case it is still tagged, which succeeds if removing the tag already
+    // removed the image
+    runtime.remove_image(&image.id)
+}
+
+/// Allocate free host ports for the port bindings with a host port of `0`
+///
+/// Ports are only allocated for containers that will be re-created and port bindings that don't
+/// already have an allocated port, so allocated ports stay the same when containers are re-created.
+/// Allocations for port bindings that have been removed are dropped.
+fn allocate_host_ports(state: &mut DaemonState) -> anyhow::Result<()> {
+    // Collect the host ports that are already in use by the unit's containers
+    let mut used_ports: HashSet<u32> = state
+        .default_container
+        .iter()
+        .chain(state.named_containers.values())
+        .flat_map(|container| {
+            container.config.ports.iter().flat_map(move |binding| {
+                let host_port = container.config.host_port(binding);
+                host_port..host_port + binding.port_count()
+            })
+        })
+        .filter(|&port| port != 0)
+        .collect();
+
+    let containers = state
+        .default_container
+        .iter_mut()
+        .chain(state.named_containers.values_mut())
+        .filter(|container| !container.is_clean() && !container.pending_removal);
+    for container in containers {
+        // Get the port bindings that need a host port allocated, mapped to their protocol
+        let auto_bindings: HashMap<String, String> = container
+            .config
+            .ports
+            .iter()
+            .filter(|binding| binding.host_port == 0)
+            .map(|binding| (binding.to_string(), binding.protocol.clone()))
+            .collect();
+
+        // Drop the allocations for removed port bindings
+        if container
+            .config
+            .allocated_host_ports
+            .keys()
+            .any(|binding| !auto_bindings.contains_key(binding))
+        {
+            container.update(|c| {
+                c.config
+                    .allocated_host_ports
+                    .retain(|binding, _| auto_bindings.contains_key(binding))
+            });
+        }
+
+        // Allocate ports for the new port bindings
+        for (binding, protocol) in auto_bindings {
+            if container.config.allocated_host_ports.contains_key(&binding) {
+                continue;
+            }
+
+            let port = find_available_port(&used_ports, &protocol)
+                .context(format!("Could not allocate host port for {}", binding))?;
+            log::debug!("Allocated host port {} for port binding {}", port, binding);
+            used_ports.insert(port);
+            container.update(|c| c.config.allocated_host_ports.insert(binding, port));
+        }
+    }
+
+    Ok(())
+}
+
+/// Find a random available host port for a protocol, such as `tcp` or `udp`, that isn't in
+/// `used_ports`
+fn find_available_port(used_ports: &HashSet<u32>, protocol: &str) -> anyhow::Result<u32> {
+    let mut rng = thread_rng();
+    let port_range = PortRange::default();
+
+    // Try a few random ports before giving up
+    for _ in 0..100 {
+        let port = rng.gen_range(port_range.min, port_range.max);
+        if !used_ports.contains(&u32::from(port)) && host_port_available(port, protocol) {
+            return Ok(port.into());
+        }
+    }
+
+    bail!("Could not find an available {} port", protocol)
+}
+
+/// Check whether a host port is free by binding to it with the given protocol
+fn host_port_available(port: u16, protocol: &str) -> bool {
+    if protocol == "udp" {
+        UdpSocket::bind(("0.0.0.0", port)).is_ok()
+    } else {
+        TcpListener::bind(("0.0.0.0", port)).is_ok()
+    }
+}
+
+/// Open the Juju ports for the host ports of the exposed container port bindings and close the
+/// ports that are no longer exposed
+///
+/// Only ports that were opened by Lucky are closed, so ports that were opened by hand are left
+/// alone.
+pub(super) fn reconcile_exposed_ports(state: &mut DaemonState) -> anyhow::Result<()> {
+    // Collect the ports that should be open
+    let wanted_ports: HashSet<String> = state
+        .default_container
+        .iter()
+        .chain(state.named_containers.values())
+        .flat_map(|container| {
+            container
+                .exposed_port_bindings()
+                .map(move |binding| (container.config.host_port(binding), binding))
+        })
+        // Skip ports that haven't been allocated yet
+        .filter(|(host_port, _)| *host_port != 0)
+        .map(|(host_port, binding)| match binding.port_count() {
+            1 => format!("{}/{}", host_port, binding.protocol),
+            count => format!(
+                "{}-{}/{}",
+                host_port,
+                host_port + count - 1,
+                binding.protocol
+            ),
+        })
+        .collect();
+
+    // Skip calling Juju if nothing has changed
+    if wanted_ports == state.exposed_ports {
+        return Ok(());
+    }
+
+    // Close the ports that are no longer exposed
+    let closed_ports: Vec<String> = state
+        .exposed_ports
+        .difference(&wanted_ports)
+        .cloned()
+        .collect();
+    for port in closed_ports {
+        log::debug!("Closing exposed container port: {}", port);
+        juju::close_port(&port)?;
+        state.exposed_ports.remove(&port);
+    }
+
+    // Open the newly exposed ports, skipping ports that have already been opened by hand
+    let opened_ports = juju::opened_ports()?;
+    for port in wanted_ports {
+        if state.exposed_ports.contains(&port) || opened_ports.contains(&port) {
+            continue;
+        }
+
+        log::debug!("Opening exposed container port: {}", port);
+        juju::open_port(&port)?;
+        state.exposed_ports.insert(port);
+    }
+
+    Ok(())
+}
+
+/// Reconcile the containers in the daemon state with the unit's containers in Docker
+///
+/// Containers in the state whose Docker container no longer exists are marked dirty so that they
+/// will be re-created the next time container updates are applied. Unknown Docker containers
+/// labeled with the unit name are adopted if they have the name of a container that has lost its
+/// Docker container, otherwise they are removed, along with unlabeled containers from older
+/// versions of Lucky that have the unit's container name prefix. Job and init containers are left
+/// alone. Returns `true` if the state was changed.
+pub(super) fn reconcile_containers(
+    runtime: &dyn ContainerRuntime,
+    state: &mut DaemonState,
+) -> anyhow::Result<bool> {
+    let unit_name = env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+
+    // Get the unit's Docker containers, including the unlabeled containers created by older
+    // versions of Lucky
+    let mut docker_containers =
+        runtime.list_containers("label", &format!("{}={}", LUCKY_UNIT_LABEL, unit_name))?;
+    for container in runtime.list_containers(
+        "name",
+        &format!("^/{}", docker_container_name_prefix(&unit_name)),
+    )? {
+        if container["Labels"][LUCKY_UNIT_LABEL].is_null() {
+            docker_containers.push(container);
+        }
+    }
+
+    // Get the unknown containers as ( id, container name, running ) tuples. Unlabeled containers
+    // don't have a container name.
+    let mut unknown_containers: Vec<(String, Option<String>, bool)> = docker_containers
+        .iter()
+        .filter(|container| container["Labels"][LUCKY_JOB_LABEL].is_null())
+        .filter_map(|container| {
+            Some((
+                container["Id"].as_str()?.to_string(),
+                container["Labels"][LUCKY_CONTAINER_LABEL]
+                    .as_str()
+                    .map(ToOwned::to_owned),
+                container["State"].as_str() == Some("running"),
+            ))
+        })
+        .collect();
+
+    let mut changed = false;
+    let container_names: Vec<Option<String>> = state
+        .named_containers
+        .keys()
+        .cloned()
+        .map(Some)
+        .chain(state.default_container.as_ref().map(|_| None))
+        .collect();
+    for container_name in container_names {
+        let container = match &container_name {
+            Some(name) => state.named_containers.get_mut(name),
+            None => state.default_container.as_mut(),
+        }
+        .expect("Missing container");
+        let log_name = container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME);
+
+        // Skip containers that still exist
+        if let Some(id) = &container.id {
+            if let Some(i) = unknown_containers.iter().position(|(x, _, _)| x == id) {
+                unknown_containers.remove(i);
+                continue;
+            }
+        }
+
+        // Adopt a Docker container with the same name, preferring running containers
+        let adopted_container = unknown_containers
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, name, _))| name.as_deref() == Some(log_name))
+            .max_by_key(|(_, (_, _, running))| *running)
+            .map(|(i, _)| i);
+        if let Some(i) = adopted_container {
+            let (id, _, _) = unknown_containers.remove(i);
+            log::info!(
+                r#"Adopting Docker container for container "{}": {}"#,
+                log_name,
+                id
+            );
+            // The adopted container already has the container's configuration so it doesn't need to
+            // be re-created
+            container.update_untracked(|info| info.id = Some(id.clone()));
+
+        // Forget the Docker container if it is missing and make sure that the container will be
+        // re-created with its current configuration
+        } else if container.id.is_some() {
+            log::warn!(
+                r#"Docker container for container "{}" is missing and will be re-created"#,
+                log_name
+            );
+            container.update_untracked(|info| info.id = None);
+            container.mark_dirty();
+
+        // Skip containers that have not been started yet
+        } else {
+            continue;
+        }
+
+        changed = true;
+    }
+
+    // Remove orphaned containers
+    for (id, name, _) in unknown_containers {
+        log::warn!(
+            r#"Removing orphaned Docker container for container "{}": {}"#,
+            name.as_deref().unwrap_or("unknown"),
+            id
+        );
+        if let Err(e) = runtime.remove_container(&id, true) {
+            log::error!("{:?}", e.context("Could not remove orphaned container"));
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Get the names of the containers in the order that they should be started, so that each
+/// container is started after the containers that it depends on
+///
+/// The default container is represented by `None`. The containers should be stopped in the
+/// reverse order.
+pub(super) fn container_start_order(state: &DaemonState) -> anyhow::Result<Vec<Option<String>>> {
+    /// Visit a container's dependencies before adding it to the order
+    fn visit<'a>(
+        name: Option<&'a str>,
+        containers: &BTreeMap<Option<&'a str>, Vec<Option<&'a str>>>,
+        visiting: &mut HashSet<Option<&'a str>>,
+        order: &mut Vec<Option<&'a str>>,
+    ) -> anyhow::Result<()> {
+        if order.contains(&name) {
+            return Ok(());
+        }
+        if !visiting.insert(name) {
+            bail!(
+                r#"Container dependencies contain a cycle involving container "{}""#,
+                name.unwrap_or(DEFAULT_CONTAINER_NAME)
+            );
+        }
+
+        for &dependency in &containers[&name] {
+            // Ignore dependencies on containers that don't exist
+            if containers.contains_key(&dependency) {
+                visit(dependency, containers, visiting, order)?;
+            }
+        }
+
+        visiting.remove(&name);
+        order.push(name);
+        Ok(())
+    }
+
+    /// Get the sorted dependencies of a container
+    fn dependencies<'a>(state: &DaemonState, container: &'a ContainerInfo) -> Vec<Option<&'a str>> {
+        let mut dependencies: Vec<_> = container
+            .depends_on
+            .keys()
+            .map(|name| resolve_dependency_name(state, name))
+            .collect();
+        dependencies.sort();
+        dependencies
+    }
+
+    // Collect the containers and their dependencies, sorted by name for a stable order
+    let mut containers = BTreeMap::new();
+    for (name, container) in &state.named_containers {
+        containers.insert(Some(name.as_str()), dependencies(state, container));
+    }
+    if let Some(container) = &state.default_container {
+        containers.insert(None, dependencies(state, container));
+    }
+
+    let mut order = Vec::new();
+    let mut visiting = HashSet::new();
+    for &name in containers.keys() {
+        visit(name, &containers, &mut visiting, &mut order)?;
+    }
+
+    Ok(order.into_iter().map(|name| name.map(Into::into)).collect())
+}
+
+/// Resolve the name of a container dependency, where `default` is the default container unless
+/// there is a named container with that name
+fn resolve_dependency_name<'a>(state: &DaemonState, name: &'a str) -> Option<&'a str> {
+    if name == DEFAULT_CONTAINER_NAME && !state.named_containers.contains_key(name) {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// Mark the containers that depend on containers that will be re-created as dirty, including
+/// indirect dependents, so that they are restarted along with their dependencies
+fn mark_dependents_dirty(state: &mut DaemonState) {
+    loop {
+        // Get the names of the containers that will be re-created
+        let dirty_containers: HashSet<Option<String>> = state
+            .named_containers
+            .iter()
+            .map(|(name, container)| (Some(name.clone()), container))
+            .chain(state.default_container.iter().map(|x| (None, x)))
+            .filter(|(_, container)| !container.is_clean())
+            .map(|(name, _)| name)
+            .collect();
+
+        // Get the running containers that depend on them
+        let dependents: Vec<Option<String>> = state
+            .named_containers
+            .iter()
+            .map(|(name, container)| (Some(name.clone()), container))
+            .chain(state.default_container.iter().map(|x| (None, x)))
+            .filter(|(_, container)| container.is_clean() && container.id.is_some())
+            .filter(|(_, container)| {
+                container.depends_on.keys().any(|dependency| {
+                    dirty_containers
+                        .contains(&resolve_dependency_name(state, dependency).map(String::from))
+                })
+            })
+            .map(|(name, _)| name)
+            .collect();
+
+        // Stop once there are no more dependents to mark
+        if dependents.is_empty() {
+            break;
+        }
+
+        for container_name in dependents {
+            log::debug!(
+                r#"Re-creating container "{}" because its dependencies will be re-created"#,
+                container_name.as_deref().unwrap_or(DEFAULT_CONTAINER_NAME)
+            );
+            match &container_name {
+                Some(name) => state.named_containers.get_mut(name),
+                None => state.default_container.as_mut(),
+            }
+            .expect("Missing container")
+            .mark_dirty();
+        }
+    }
+}
+
+/// Get the dependencies of a container that must be healthy before the container is started as
+/// ( dependency name, Docker container id, timeout ) tuples
+fn dependencies_to_wait_for(
+    state: &DaemonState,
+    container_name: Option<&str>,
+) -> Vec<(String, String, Duration)> {
+    let container = match container_name {
+        Some(name) => state.named_containers.get(name),
+        None => state.default_container.as_ref(),
+    }
+    .expect("Missing container");
+
+    // Skip containers that won't be started
+    if container.is_clean() || container.pending_removal {
+        return vec![];
+    }
+
+    container
+        .depends_on
+        .iter()
+        .filter(|(_, &wait)| wait)
+        .filter_map(|(dependency_name, _)| {
+            let dependency = match resolve_dependency_name(state, dependency_name) {
+                Some(name) => state.named_containers.get(name),
+                None => state.default_container.as_ref(),
+            }?;
+            let timeout = dependency
+                .health_check_timeout
+                .unwrap_or(DEFAULT_DEPENDENCY_TIMEOUT);
+            Some((
+                dependency_name.clone(),
+                dependency.id.clone()?,
+                Duration::from_secs(timeout),
+            ))
+        })
+        .collect()
+}
+
+/// Wait for the dependencies of a container that must be healthy before the container is started
+fn wait_for_dependencies(
+    runtime: &dyn ContainerRuntime,
+    container_name: Option<&str>,
+    dependencies: &[(String, String, Duration)],
+) -> anyhow::Result<()> {
+    for (dependency_name, dependency_id, timeout) in dependencies {
+        wait_for_healthy(runtime, dependency_id, *timeout).context(format!(
+            r#"Dependency "{}" of container "{}" did not become healthy"#,
+            dependency_name,
+            container_name.unwrap_or(DEFAULT_CONTAINER_NAME)
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Store a container that updates were applied to while the state lock was released
+///
+/// `applied_info` is the container info that the updates were applied from. If the container was
+/// changed while the updates were being applied, the new changes are kept and the container is
+/// left dirty so that they are applied the next time that container updates are applied.
+fn store_applied_container(
+    state: &mut DaemonState,
+    container_name: Option<&str>,
+    applied_info: &ContainerInfo,
+    applied_container: Cd<ContainerInfo>,
+) {
+    let container = match get_container_mut(state, container_name) {
+        Some(container) => container,
+        // The container was removed while the updates were being applied
+        None => return,
+    };
+
+    if **container == *applied_info {
+        *container = applied_container;
+    } else {
+        // Record the Docker container that is now running without discarding the new changes
+        let id = if applied_container.pending_removal {
+            None
+        } else {
+            applied_container.id.clone()
+        };
+        let image_digest = applied_container.image_digest.clone();
+        container.update_untracked(|info| {
+            info.id = id.clone();
+            info.image_digest = image_digest.clone();
+        });
+        container.mark_dirty();
+    }
+}
+
+/// Get the id of the internal script status used to report that a container has exited
+pub(super) fn container_status_id(container_name: Option<&str>) -> String {
+    format!(
+        "__lucky::container::{}__",
+        container_name.unwrap_or(DEFAULT_CONTAINER_NAME)
+    )
+}
+
+/// Get the registry credentials to use when pulling a container's image, if any
+///
+/// Credentials from the container's registry auth config key take precedence over credentials
+/// from a registry login.
+fn get_registry_credentials(
+    state: &DaemonState,
+    container_info: &ContainerInfo,
+) -> anyhow::Result<Option<RegistryCredentials>> {
+    image_registry_credentials(
+        state,
+        &container_info.config.image,
+        container_info.registry_auth_config_key.as_deref(),
+    )
+}
+
+/// Get the registry credentials to use when pulling an image, if any
+///
+/// Credentials from the `registry_auth_config_key` take precedence over credentials from a
+/// registry login.
+fn image_registry_credentials(
+    state: &DaemonState,
+    image: &str,
+    registry_auth_config_key: Option<&str>,
+) -> anyhow::Result<Option<RegistryCredentials>> {
+    if let Some(config_key) = registry_auth_config_key {
+        // Parse the credentials from the charm config
+        let value = state.charm_config.get(config_key).ok_or_else(|| {
+            format_err!(
+                "Registry auth config key \"{}\" does not exist in the charm config",
+                config_key
+            )
+        })?;
+        let credentials = match &**value {
+            JsonValue::String(json) => serde_json::from_str(json),
+            other => serde_json::from_value(other.clone()),
+        }
+        .context(format!(
+            "Could not parse registry credentials from config key \"{}\"",
+            config_key
+        ))?;
+
+        Ok(Some(credentials))
+    } else {
+        Ok(state
+            .registry_credentials
+            .get(crate::docker::image_registry(image))
+            .cloned())
+    }
+}
+
+/// Pull an image if it is required by the pull policy
+fn pull_image(
+    runtime: &dyn ContainerRuntime,
+    image: &str,
+    pull_policy: PullPolicy,
+    registry_credentials: Option<&RegistryCredentials>,
+) -> anyhow::Result<()> {
+    let should_pull = match pull_policy {
+        PullPolicy::Always => true,
+        PullPolicy::IfNotPresent => runtime.inspect_image(image)?.is_none(),
+        PullPolicy::Never => false,
+    };
+    if !should_pull {
+        return Ok(());
+    }
+
+    // Pull the image
+    log::debug!("Pulling container image: {}", image);
+    let auth = registry_credentials
+        .map(|credentials| credentials.to_registry_auth(crate::docker::image_registry(image)))
+        .transpose()?;
+    runtime.pull_image(image, auth)
+}
+
+/// Pull a container's image and pin the container to the image's digest
+///
+/// The image reference will be rewritten to `repository@sha256:...` so that the container will
+/// always run exactly the same image, even if the tag is moved.
+pub(super) fn pin_container_image(
+    daemon: &LuckyDaemon,
+    state: &mut DaemonState,
+    container_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let container = match container_name {
+        Some(name) => state.named_containers.get(name),
+        None => state.default_container.as_ref(),
+    }
+    .ok_or_else(|| format_err!("Container does not exist"))?;
+    let image = container.config.image.clone();
+
+    // Pull the image
+    let registry_credentials = get_registry_credentials(state, container)?;
+    pull_image(
+        &*daemon.runtime,
+        &image,
+        container.pull_policy,
+        registry_credentials.as_ref(),
+    )?;
+
+    // Get the image digest
+    let digest = daemon.runtime.image_digest(&image)?.ok_or_else(|| {
+        format_err!(
+            "Could not pin image {}: the image does not have a registry digest",
+            image
+        )
+    })?;
+    let pinned_image = format!("{}@{}", crate::docker::image_repository(&image), digest);
+    log::debug!("Pinning container image {} to {}", image, pinned_image);
+
+    // Set the pinned image on the container
+    let container = match container_name {
+        Some(name) => state.named_containers.get_mut(name),
+        None => state.default_container.as_mut(),
+    }
+    .expect("Missing container");
+    container.update(|c| {
+        c.config.image = pinned_image;
+        c.image_digest = Some(digest);
+    });
+
+    Ok(())
+}
+
+/// The result of applying updates to a container
+enum UpdateOutcome {
+    /// The container was already up-to-date or was updated successfully
+    Applied,
+    /// The new container failed and the container was rolled back to its previous configuration
+    RolledBack(anyhow::Error),
+}
+
 fn apply_updates(
     daemon: &LuckyDaemon,
+    container_name: Option<&str>,
     container_info: &mut Cd<ContainerInfo>,
-) -> anyhow::Result<()> {
+    registry_credentials: Option<&RegistryCredentials>,
+    proxy_settings: Option<&ProxySettings>,
+) -> anyhow::Result<UpdateOutcome> {
     // Skip apply if container config is unchanged since last apply
     if container_info.is_clean() {
-        return Ok(());
+        return Ok(UpdateOutcome::Applied);
+    }
+
+    let runtime = &*daemon.runtime;
+    let old_container_id = container_info.id.clone();
+
+    // If this container is meant to be removed
+    if container_info.pending_removal {
+        if let Some(id) = &old_container_id {
+            remove_docker_container(runtime, id, true)?;
+        }
+        remove_pending_volumes(runtime, container_info);
+
+        return Ok(UpdateOutcome::Applied);
+    }
+
+    // Deploy the new container, keeping track of how far we got so that we can roll back
+    let mut new_container_id = None;
+    let mut old_container_stopped = false;
+    if let Err(e) = deploy_container(
+        daemon,
+        container_name,
+        container_info,
+        registry_credentials,
+        proxy_settings,
+        &mut new_container_id,
+        &mut old_container_stopped,
+    ) {
+        // Remove the new container if it was created
+        if let Some(id) = &new_container_id {
+            if let Err(e) = remove_docker_container(runtime, id, true) {
+                log::warn!("{:?}", e.context("Could not remove failed container"));
+            }
+        }
+
+        // If there isn't a previous container to roll back to, there is nothing more we can do
+        let old_container_id = match old_container_id {
+            Some(id) => id,
+            None => return Err(e),
+        };
+
+        // Restart the old container if it was stopped
+        log::warn!("Rolling back container update: {:?}", e);
+        if old_container_stopped {
+            log::debug!("Restarting previous container: {}", old_container_id);
+            runtime
+                .start_container(&old_container_id)
+                .context("Could not restart previous container while rolling back update")?;
+        }
+
+        // Restore the previous container configuration
+        container_info.revert();
+
+        return Ok(UpdateOutcome::RolledBack(e));
     }
+    let new_container_id = new_container_id.expect("Missing new container id");
 
-    // Get the docker connection
-    let docker_conn = daemon.get_docker_conn()?;
-    let docker_conn = docker_conn.lock().unwrap();
-    let containers = docker_conn.containers();
-    let images = docker_conn.images();
+    // Remove the old container now that the new one is running
+    if let Some(id) = &old_container_id {
+        remove_docker_container(runtime, id, !old_container_stopped)?;
+    }
+
+    // Remove Docker volumes that were pending removal now that the old container isn't using them
+    remove_pending_volumes(runtime, container_info);
+
+    // Forward the container logs to the Juju log if enabled
+    if daemon.lucky_metadata.forward_container_logs {
+        forward_container_logs(runtime, &new_container_id, container_name)?;
+    }
+
+    // Mark container_info as "clean" and up-to-date with the system config
+    container_info.update(|info| info.id = Some(new_container_id));
+    container_info.clean();
+
+    Ok(UpdateOutcome::Applied)
+}
 
-    // If the container has already been deployed
-    if let Some(id) = &container_info.id {
-        // Remove the container
-        let container = containers.get(&id);
+/// Pull the image for and start a new container according to the container's update strategy
+///
+/// The ID of the new container is set as soon as it has been created, and `old_container_stopped`
+/// is set if the old container was stopped, so that the update can be rolled back if this fails.
+fn deploy_container(
+    daemon: &LuckyDaemon,
+    container_name: Option<&str>,
+    container_info: &mut Cd<ContainerInfo>,
+    registry_credentials: Option<&RegistryCredentials>,
+    proxy_settings: Option<&ProxySettings>,
+    new_container_id: &mut Option<String>,
+    old_container_stopped: &mut bool,
+) -> anyhow::Result<()> {
+    let runtime = &*daemon.runtime;
+    let image_name = container_info.config.image.clone();
+
+    // Pull the image if necessary
+    pull_image(
+        runtime,
+        &image_name,
+        container_info.pull_policy,
+        registry_credentials,
+    )
+    .context(format!("Could not pull image {}", image_name))?;
+
+    // Record the digest of the image that the container is run with
+    let image_digest = runtime.image_digest(&image_name)?;
+    container_info.update(|info| info.image_digest = image_digest);
+
+    // Run the init containers, which must all succeed before the container is started
+    for (i, init_config) in container_info.init_containers.iter().enumerate() {
+        let init_name = format!(
+            "{}-init-{}",
+            container_name.unwrap_or(DEFAULT_CONTAINER_NAME),
+            i
+        );
+        log::info!("Running init container: {}", init_name);
+
+        // Only use the container's registry credentials for images from the same registry
+        let init_credentials = registry_credentials.filter(|_| {
+            crate::docker::image_registry(&init_config.image)
+                == crate::docker::image_registry(&image_name)
+        });
+        let exit_code = run_job_container(
+            daemon,
+            init_config,
+            &init_name,
+            init_credentials,
+            proxy_settings,
+        )
+        .context(format!(r#"Could not run init container "{}""#, init_name))?;
+        if exit_code != 0 {
+            bail!(
+                r#"Init container "{}" exited non-zero: {}"#,
+                init_name,
+                exit_code
+            );
+        }
+    }
+
+    // Create the container
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let mut docker_options = container_info.config.to_container_options(
+        &daemon.charm_dir,
+        &daemon.lucky_data_dir,
+        &daemon.socket_path,
+        &unit_name,
+        container_name,
+        proxy_settings,
+    )?;
+    docker_options.name = Some(docker_container_name(&unit_name));
+
+    log::trace!("Creating container with options: {:#?}", docker_options);
+    let container_id = runtime
+        .create_container(&docker_options)
+        .context("Could not create container")?;
+    *new_container_id = Some(container_id.clone());
+
+    // Stop the old container first if required by the update strategy
+    if container_info.update_strategy == UpdateStrategy::StopFirst {
+        if let Some(old_id) = &container_info.id {
+            log::debug!("Stopping container: {}", old_id);
+            runtime.stop_container(old_id, Duration::from_secs(10))?;
+            *old_container_stopped = true;
+        }
+    }
+
+    // Start the container
+    log::debug!("Starting container: {}", container_id);
+    runtime
+        .start_container(&container_id)
+        .context("Could not start container")?;
+
+    // Wait for the container's health check to pass, or make sure that it doesn't crash right
+    // after it is started if it doesn't have a health check timeout
+    if let Some(timeout) = container_info.health_check_timeout {
+        wait_for_healthy(runtime, &container_id, Duration::from_secs(timeout))?;
+    } else {
+        let grace_period = container_info
+            .startup_grace_period
+            .unwrap_or(DEFAULT_STARTUP_GRACE_PERIOD);
+        wait_for_startup(runtime, &container_id, Duration::from_secs(grace_period))?;
+    }
+
+    Ok(())
+}
+
+/// Generate a name for a new Docker container with a random suffix
+fn docker_container_name(unit_name: &str) -> String {
+    let mut rng = thread_rng();
+    let mut suffix = String::with_capacity(8);
+    for _ in 0..8 {
+        suffix.push(
+            CONTAINER_SUFFIX_CHARS
+                .chars()
+                .choose(&mut rng)
+                .expect("Empty suffix chars"),
+        );
+    }
+
+    format!("{}{}", docker_container_name_prefix(unit_name), suffix)
+}
+
+/// Get the prefix of the names of the unit's Docker containers, such as `lucky_app_0_`
+fn docker_container_name_prefix(unit_name: &str) -> String {
+    format!("lucky_{}_", unit_name.replace("/", "_"))
+}
+
+/// Wait for a container's health check to pass
+///
+/// If the container doesn't have a health check, it will be considered healthy as long as it
+/// is still running.
+fn wait_for_healthy(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    log::debug!("Waiting for container to become healthy: {}", container_id);
+    let start = Instant::now();
+
+    loop {
+        let details = runtime
+            .inspect_container(container_id)?
+            .ok_or_else(|| format_err!("Container was removed before it became healthy"))?;
+        let container_state = &details["State"];
+
+        if !container_state["Running"].as_bool().unwrap_or(false) {
+            bail!(
+                "Container exited with code {} before it became healthy",
+                container_state["ExitCode"]
+            );
+        }
+
+        match container_state["Health"]["Status"].as_str() {
+            // Containers without a health check are ready once they are running
+            None | Some("healthy") => return Ok(()),
+            Some("unhealthy") => bail!("Container health check failed"),
+            _ => (),
+        }
 
+        if start.elapsed() > timeout {
+            bail!(
+                "Container did not become healthy within {} seconds",
+                timeout.as_secs()
+            );
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Wait for the startup grace period and make sure that the container is still running
+///
+/// Containers that have exited or been restarted by their restart policy during the grace period
+/// are considered failed.
+fn wait_for_startup(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    grace_period: Duration,
+) -> anyhow::Result<()> {
+    thread::sleep(grace_period);
+
+    let details = runtime
+        .inspect_container(container_id)?
+        .ok_or_else(|| format_err!("Container was removed right after it was started"))?;
+    let container_state = &details["State"];
+
+    if !container_state["Running"].as_bool().unwrap_or(false)
+        || container_state["Restarting"].as_bool().unwrap_or(false)
+        || details["RestartCount"].as_i64().unwrap_or(0) > 0
+    {
+        bail!(
+            "Container exited with code {} within {} seconds of being started",
+            container_state["ExitCode"],
+            grace_period.as_secs()
+        );
+    }
+
+    Ok(())
+}
+
+/// Stop, if requested, and remove a Docker container
+fn remove_docker_container(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    stop: bool,
+) -> anyhow::Result<()> {
+    if stop {
         // TODO: handle NOT MODIFIED error response
-        log::debug!("Stopping container: {}", id);
-        block_on(container.stop(Some(Duration::from_secs(10))))?;
-        log::debug!("Removing container: {}", id);
-        block_on(container.delete())?;
-
-        // Clear the containers ID
-        container_info.update(|info| info.id = None);
-    }
-
-    // If this contianer was not meant to be removed
-    if !container_info.pending_removal {
-        let image_name = container_info.config.image.clone();
-
-        if container_info.pull_image {
-            // Pull the image
-            log::debug!("Pulling container image: {}", image_name);
-            block_on(
-                images
-                    .pull(&PullOptions::builder().image(image_name).build())
-                    .collect(),
-            )?;
-        }
-
-        // Create the container
-        let mut docker_options = container_info.config.to_container_options(
-            &daemon.charm_dir,
-            &daemon.lucky_data_dir,
-            &daemon.socket_path,
-        )?;
-        let unit_name = std::env::var("JUJU_UNIT_NAME")
-            .context("Env var JUJU_UNIT_NAME not readable!")?
-            .replace("/", "_");
-        docker_options.name = Some(format!("lucky_{}_{}", unit_name, {
-            // Generate random suffix
-            let mut rng = thread_rng();
-            let mut buffer = String::with_capacity(8);
-            for _ in 0..8 {
-                buffer.push(
-                    CONTAINER_SUFFIX_CHARS
-                        .chars()
-                        .choose(&mut rng)
-                        .expect("Empty suffix chars"),
+        log::debug!("Stopping container: {}", container_id);
+        runtime.stop_container(container_id, Duration::from_secs(10))?;
+    }
+    log::debug!("Removing container: {}", container_id);
+    runtime.remove_container(container_id, false)?;
+
+    Ok(())
+}
+
+/// Remove the Docker volumes that are pending removal from the container
+fn remove_pending_volumes(runtime: &dyn ContainerRuntime, container_info: &mut Cd<ContainerInfo>) {
+    if container_info.pending_volume_removals.is_empty() {
+        return;
+    }
+
+    for volume in &container_info.pending_volume_removals {
+        log::debug!("Removing Docker volume: {}", volume);
+        if let Err(e) = runtime.remove_volume(volume) {
+            log::warn!(
+                "{:?}",
+                e.context(format!("Could not remove volume: {}", volume))
+            );
+        }
+    }
+    container_info.update(|info| info.pending_volume_removals.clear());
+}
+
+/// Get a snapshot of a container's resource usage
+///
+/// The values are calculated the same way that `docker stats` calculates them.
+pub(super) fn container_resource_stats(
+    runtime: &dyn ContainerRuntime,
+    container_name: Option<&str>,
+    container_id: &str,
+) -> anyhow::Result<rpc::ContainerResourceStats> {
+    let details = runtime
+        .inspect_container(container_id)?
+        .ok_or_else(|| format_err!("Container does not exist: {}", container_id))?;
+    let stats = runtime.container_stats(container_id)?;
+    let int = |value: &JsonValue| value.as_i64().unwrap_or(0);
+
+    // Get the CPU usage as a percentage of one CPU since the previous sample
+    let cpu_stats = &stats["cpu_stats"];
+    let precpu_stats = &stats["precpu_stats"];
+    let cpu_delta = int(&cpu_stats["cpu_usage"]["total_usage"])
+        - int(&precpu_stats["cpu_usage"]["total_usage"]);
+    let system_delta = int(&cpu_stats["system_cpu_usage"]) - int(&precpu_stats["system_cpu_usage"]);
+    let online_cpus = cpu_stats["online_cpus"]
+        .as_i64()
+        .or_else(|| {
+            cpu_stats["cpu_usage"]["percpu_usage"]
+                .as_array()
+                .map(|cpus| cpus.len() as i64)
+        })
+        .unwrap_or(1);
+    let cpu_percent = if cpu_delta > 0 && system_delta > 0 {
+        cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+    } else {
+        0.0
+    };
+
+    // Get the memory usage, not counting the page cache
+    let memory_stats = &stats["memory_stats"];
+    let cache = memory_stats["stats"]["inactive_file"]
+        .as_i64()
+        .or_else(|| memory_stats["stats"]["cache"].as_i64())
+        .unwrap_or(0);
+    let memory_usage = (int(&memory_stats["usage"]) - cache).max(0);
+
+    // Add up the IO of all of the container's networks
+    let (network_rx, network_tx) = stats["networks"]
+        .as_object()
+        .map(|networks| {
+            networks.values().fold((0, 0), |(rx, tx), network| {
+                (
+                    rx + int(&network["rx_bytes"]),
+                    tx + int(&network["tx_bytes"]),
+                )
+            })
+        })
+        .unwrap_or((0, 0));
+
+    // Add up the block IO of all of the container's devices
+    let (block_read, block_write) = stats["blkio_stats"]["io_service_bytes_recursive"]
+        .as_array()
+        .map(|entries| {
+            entries.iter().fold((0, 0), |(read, write), entry| {
+                match entry["op"].as_str().map(str::to_lowercase).as_deref() {
+                    Some("read") => (read + int(&entry["value"]), write),
+                    Some("write") => (read, write + int(&entry["value"])),
+                    _ => (read, write),
+                }
+            })
+        })
+        .unwrap_or((0, 0));
+
+    Ok(rpc::ContainerResourceStats {
+        container_name: container_name.map(Into::into),
+        running: details["State"]["Running"].as_bool().unwrap_or(false),
+        cpu_percent,
+        memory_usage,
+        memory_limit: int(&memory_stats["limit"]),
+        network_rx,
+        network_tx,
+        block_read,
+        block_write,
+        restart_count: int(&details["RestartCount"]),
+    })
+}
+
+/// Forward the stderr output of a container to the Juju log
+///
+/// This spawns a thread that will forward the logs until the container is removed.
+fn forward_container_logs(
+    runtime: &dyn ContainerRuntime,
+    container_id: &str,
+    container_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let unit_name =
+        std::env::var("JUJU_UNIT_NAME").context("Env var JUJU_UNIT_NAME not readable!")?;
+    let container_name = container_name.unwrap_or(DEFAULT_CONTAINER_NAME).to_string();
+
+    // Follow the container's stderr, starting now
+    let receiver = runtime.container_logs(
+        container_id,
+        &ContainerLogOptions {
+            follow: true,
+            tail: None,
+            since: Some(Utc::now().timestamp()),
+            stdout: false,
+            stderr: true,
+        },
+    )?;
+
+    log::debug!("Forwarding logs for container: {}", container_name);
+    thread::Builder::new()
+        .name(format!("forward-logs-{}", container_name))
+        .spawn(move || {
+            // Wait for a line of output. This will return an error when the container is removed.
+            while let Ok(line) = receiver.recv() {
+                // Collect lines for a little while so that we don't have to run `juju-run` for
+                // every line
+                let mut lines = vec![line];
+                let deadline = Instant::now() + LOG_FORWARD_INTERVAL;
+                while lines.len() < LOG_FORWARD_MAX_LINES {
+                    match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    {
+                        Ok(line) => lines.push(line),
+                        Err(_) => break,
+                    }
+                }
+
+                // Log the lines to the Juju log
+                let messages: Vec<String> = lines
+                    .into_iter()
+                    .map(|line| format!("[container:{}] {}", container_name, line.text))
+                    .collect();
+                crate::juju::juju_log_outside_hook(&unit_name, &messages, false).unwrap_or_else(
+                    |e| log::warn!("Could not forward container logs to Juju: {:?}", e),
                 );
             }
-            buffer
-        }));
+        })
+        .context("Could not spawn log forwarding thread")?;
 
-        log::trace!("Creating container with options: {:#?}", docker_options);
-        let create_info = block_on(containers.create(&docker_options))?;
+    Ok(())
+}
 
-        // Start the container
-        log::debug!("Starting container: {}", create_info.id);
-        let container = containers.get(&create_info.id);
-        block_on(container.start())?;
+/// Start forwarding the logs of all running containers to the Juju log
+///
+/// This is used to resume log forwarding when the daemon is restarted.
+pub(super) fn forward_all_container_logs(daemon: &LuckyDaemon) -> anyhow::Result<()> {
+    let state = daemon.state.read().unwrap();
 
-        // Mark container_info as "clean" and up-to-date with the system config
-        container_info.update(|info| info.id = Some(create_info.id));
-        container_info.clean();
+    // Collect the running containers
+    let mut running_containers: Vec<(Option<&str>, &str)> = state
+        .named_containers
+        .iter()
+        .filter_map(|(name, info)| info.id.as_deref().map(|id| (Some(name.as_str()), id)))
+        .collect();
+    if let Some(id) = state
+        .default_container
+        .as_ref()
+        .and_then(|info| info.id.as_deref())
+    {
+        running_containers.push((None, id));
+    }
+
+    for (name, id) in running_containers {
+        forward_container_logs(&*daemon.runtime, id, name)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a container that depends on the given containers
+    fn container_depending_on(dependencies: &[&str]) -> Cd<ContainerInfo> {
+        let mut container = ContainerInfo::new("alpine");
+        container.depends_on = dependencies
+            .iter()
+            .map(|&name| (name.to_string(), false))
+            .collect();
+        container.into()
+    }
+
+    #[test]
+    fn container_start_order_starts_dependencies_first() {
+        let mut state = DaemonState::default();
+        state.default_container = Some(container_depending_on(&["db"]));
+        state
+            .named_containers
+            .insert("web".into(), container_depending_on(&["default", "db"]));
+        state
+            .named_containers
+            .insert("db".into(), container_depending_on(&[]));
+
+        assert_eq!(
+            container_start_order(&state).unwrap(),
+            vec![Some("db".to_string()), None, Some("web".to_string())]
+        );
+    }
+
+    #[test]
+    fn container_start_order_ignores_missing_dependencies() {
+        let mut state = DaemonState::default();
+        state
+            .named_containers
+            .insert("web".into(), container_depending_on(&["default", "cache"]));
+
+        assert_eq!(
+            container_start_order(&state).unwrap(),
+            vec![Some("web".to_string())]
+        );
+    }
+
+    #[test]
+    fn container_start_order_rejects_cycles() {
+        let mut state = DaemonState::default();
+        state.default_container = Some(container_depending_on(&["web"]));
+        state
+            .named_containers
+            .insert("web".into(), container_depending_on(&["db"]));
+        state
+            .named_containers
+            .insert("db".into(), container_depending_on(&["default"]));
+
+        assert!(container_start_order(&state).is_err());
+    }
+}
@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How long container events are still ignored after the container runtime has been restarted,
+/// giving the runtime time to start the containers that it stopped
+const EVENT_SUPPRESSION_GRACE_PERIOD: Duration = Duration::from_secs(10);
 
 #[derive(Serialize, Deserialize, Clone)]
 /// A change detecting container for other types
@@ -14,7 +20,7 @@ use std::ops::Deref;
 /// version and will return `true` if the clone and the original are the same. This means that if
 /// you modify the type and the set it back to what it was previously, `is_clean()` will still
 /// return `true`.
-/// 
+///
 /// > **Note:** Newly created `Cd`'s start off "dirty" and `is_clean()` will return false. You
 /// > can also force any `Cd` to register as dirty, regardless of whether or not the inner type has
 /// > changed by running `mark_dirty()`.
@@ -76,6 +82,12 @@ impl<T: Clone + PartialEq> Cd<T> {
         self.force_dirty = true;
     }
 
+    /// Discard any updates made since the last run of `clean()`, making this object clean
+    pub fn revert(&mut self) {
+        self.new_inner = None;
+        self.force_dirty = false;
+    }
+
     /// Consumes the `Cd` and converts to the inner type
     pub fn into_inner(self) -> T {
         // Return the latest updated inner type if it exists
@@ -114,6 +126,21 @@ impl<T: Clone + PartialEq> Cd<T> {
             ret
         }
     }
+
+    /// Update the inner type without the change being detected by `is_clean()`
+    ///
+    /// The closure is run on both the original inner type and, if it exists, the updated inner
+    /// type. This is used for values that don't require the inner type to be re-applied when they
+    /// change.
+    pub fn update_untracked<F>(&mut self, update_inner: F)
+    where
+        F: Fn(&mut T),
+    {
+        update_inner(&mut self.inner);
+        if let Some(new_inner) = self.new_inner.as_mut() {
+            update_inner(new_inner);
+        }
+    }
 }
 
 impl<T: Clone + PartialEq> Deref for Cd<T> {
@@ -140,3 +167,39 @@ impl<T: Clone + PartialEq + std::fmt::Debug> std::fmt::Debug for Cd<T> {
         write!(f, "{:?}", self.inner)
     }
 }
+
+#[derive(Default)]
+/// Tracks when container events should be ignored
+///
+/// When Lucky restarts the container runtime, such as to apply new daemon settings, all of the
+/// containers are stopped along with it. The events for those containers are ignored so that they
+/// aren't reported as having died.
+pub(crate) struct EventSuppression {
+    /// The number of runtime restarts in progress and the time at which events stop being ignored
+    /// after the last restart
+    inner: Mutex<(usize, Option<Instant>)>,
+}
+
+impl EventSuppression {
+    /// Run a closure that may restart the container runtime, ignoring container events until
+    /// shortly after it returns
+    pub fn suppress_during<F, U>(&self, f: F) -> U
+    where
+        F: FnOnce() -> U,
+    {
+        self.inner.lock().unwrap().0 += 1;
+        let result = f();
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.0 -= 1;
+        inner.1 = Some(Instant::now() + EVENT_SUPPRESSION_GRACE_PERIOD);
+
+        result
+    }
+
+    /// Whether or not container events should currently be ignored
+    pub fn is_suppressed(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.0 > 0 || inner.1.map_or(false, |until| Instant::now() < until)
+    }
+}
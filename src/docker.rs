@@ -1,21 +1,41 @@
 //! Contains tools for installing and interracting with Docker
-use anyhow::{bail, Context};
+use anyhow::{bail, format_err, Context};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use shiplift::builder::ContainerOptions;
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use shiplift::builder::{ContainerOptions, RegistryAuth};
 use shrinkwraprs::Shrinkwrap;
+use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
+use walkdir::WalkDir;
 
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::fs;
-use std::io::Write;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use crate::process::{cmd_exists, run_cmd, run_cmd_with_retries};
+use crate::rpc;
 
 use crate::VOLUME_DIR;
 
+pub(crate) mod api;
+pub(crate) mod runtime;
+
+/// The label used to identify the unit that a Lucky container belongs to
+pub(crate) const LUCKY_UNIT_LABEL: &str = "lucky.unit";
+/// The label used to identify the Lucky name of a container
+pub(crate) const LUCKY_CONTAINER_LABEL: &str = "lucky.container";
+/// The label used to identify job and init containers, which are run to completion, and the name
+/// of their job
+pub(crate) const LUCKY_JOB_LABEL: &str = "lucky.job";
+/// The label used to identify the Lucky name of a network
+pub(crate) const LUCKY_NETWORK_LABEL: &str = "lucky.network";
+/// The name used to refer to the default container in labels and logs
+pub(crate) const DEFAULT_CONTAINER_NAME: &str = "default";
+
 /// A struct made of a container definition and the container id
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub(crate) struct ContainerInfo {
@@ -23,8 +43,51 @@ pub(crate) struct ContainerInfo {
     pub id: Option<String>,
     /// Marks this container as pending removal
     pub pending_removal: bool,
-    /// Whether or not to pull the Docker image before running it
-    pub pull_image: bool,
+    /// When to pull the Docker image before running it. This is read from the `pull_image` bool
+    /// in daemon states from older versions of Lucky.
+    #[serde(
+        default,
+        alias = "pull_image",
+        deserialize_with = "deserialize_pull_policy"
+    )]
+    pub pull_policy: PullPolicy,
+    /// The digest of the image that the container was last run with, such as `sha256:...`
+    #[serde(default)]
+    pub image_digest: Option<String>,
+    /// The charm config key containing the registry credentials used to pull the image
+    #[serde(default)]
+    pub registry_auth_config_key: Option<String>,
+    /// Docker volumes that should be removed the next time the container is updated
+    #[serde(default)]
+    pub pending_volume_removals: HashSet<String>,
+    /// The image built from the charm's `container_images` dir, if the container uses one
+    #[serde(default)]
+    pub image_build: Option<ImageBuild>,
+    /// How to replace the container when it is updated
+    #[serde(default)]
+    pub update_strategy: UpdateStrategy,
+    /// How long to wait, in seconds, for the container's health check to pass after it has been
+    /// started. If this is `None` the container is considered ready as soon as it is started.
+    #[serde(default)]
+    pub health_check_timeout: Option<u64>,
+    /// How long, in seconds, a new container without a health check timeout must keep running after
+    /// it is started for an update to be considered successful. A default is used if this is `None`.
+    #[serde(default)]
+    pub startup_grace_period: Option<u64>,
+    /// The containers that this container depends on, mapped to whether or not to wait for the
+    /// dependency to be healthy before starting this container. The default container is named
+    /// `default`.
+    #[serde(default)]
+    pub depends_on: HashMap<String, bool>,
+    /// Containers that are run to completion, in order, before the container is started
+    #[serde(default)]
+    pub init_containers: Vec<ContainerConfig>,
+    /// Whether or not to open all of the container's host ports through Juju
+    #[serde(default)]
+    pub auto_expose: bool,
+    /// Port bindings whose host ports should be opened through Juju
+    #[serde(default)]
+    pub exposed_ports: HashSet<PortBinding>,
     /// The definition for the desired state of the container. This should match the actual state
     /// of the container if `dirty` is `false`.
     pub config: ContainerConfig,
@@ -35,10 +98,269 @@ impl ContainerInfo {
         ContainerInfo {
             id: None,
             pending_removal: false,
-            pull_image: true,
+            pull_policy: PullPolicy::default(),
+            image_digest: None,
+            registry_auth_config_key: None,
+            pending_volume_removals: HashSet::new(),
+            image_build: None,
+            update_strategy: UpdateStrategy::default(),
+            health_check_timeout: None,
+            startup_grace_period: None,
+            depends_on: HashMap::new(),
+            init_containers: Vec::new(),
+            auto_expose: false,
+            exposed_ports: HashSet::new(),
             config: ContainerConfig::new(image),
         }
     }
+
+    /// Get the port bindings whose host ports should be opened through Juju
+    pub fn exposed_port_bindings(&self) -> impl Iterator<Item = &PortBinding> {
+        let auto_expose = self.auto_expose;
+        let exposed_ports = &self.exposed_ports;
+        self.config
+            .ports
+            .iter()
+            .filter(move |binding| auto_expose || exposed_ports.contains(binding))
+    }
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    AsRefStr,
+    EnumString,
+    EnumVariantNames,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+/// When to pull a container's image
+pub(crate) enum PullPolicy {
+    /// Pull the image every time the container is re-created
+    Always,
+    /// Only pull the image if it doesn't exist on the host
+    IfNotPresent,
+    /// Never pull the image
+    Never,
+}
+
+impl Default for PullPolicy {
+    fn default() -> Self {
+        PullPolicy::IfNotPresent
+    }
+}
+
+/// Deserialize a pull policy, also accepting the `pull_image` bool that was used before pull
+/// policies were added
+///
+/// `pull_image: true` pulled the image every time the container was re-created and `false` never
+/// pulled it.
+fn deserialize_pull_policy<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<PullPolicy, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PullPolicyOrBool {
+        Policy(PullPolicy),
+        Bool(bool),
+    }
+
+    Ok(match PullPolicyOrBool::deserialize(deserializer)? {
+        PullPolicyOrBool::Policy(policy) => policy,
+        PullPolicyOrBool::Bool(true) => PullPolicy::Always,
+        PullPolicyOrBool::Bool(false) => PullPolicy::Never,
+    })
+}
+
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    AsRefStr,
+    EnumString,
+    EnumVariantNames,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+/// How to replace a container when its configuration is updated
+///
+/// With either strategy the new image is pulled and the new container is created before the old
+/// container is touched, and the old container is restored if the new one fails to start.
+pub(crate) enum UpdateStrategy {
+    /// Stop the old container before starting the new one. This is required when the containers
+    /// would conflict with each other, such as when they bind the same host ports.
+    StopFirst,
+    /// Start the new container and wait for it to be ready before removing the old one
+    StartFirst,
+}
+
+impl Default for UpdateStrategy {
+    fn default() -> Self {
+        UpdateStrategy::StopFirst
+    }
+}
+
+/// Credentials used to authenticate with a container registry
+///
+/// This uses the same field names as Docker's `X-Registry-Auth` header so that credentials can be
+/// provided as JSON in the charm config.
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
+pub(crate) struct RegistryCredentials {
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    #[serde(rename = "identitytoken")]
+    pub identity_token: Option<String>,
+    /// The registry server address. Defaults to the registry in the image name.
+    #[serde(default)]
+    #[serde(rename = "serveraddress")]
+    pub server_address: Option<String>,
+}
+
+impl RegistryCredentials {
+    /// Get the shiplift registry auth for these credentials
+    pub fn to_registry_auth(&self, registry: &str) -> anyhow::Result<RegistryAuth> {
+        if let Some(token) = &self.identity_token {
+            Ok(RegistryAuth::token(token.as_str()))
+        } else if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            Ok(RegistryAuth::builder()
+                .username(username.as_str())
+                .password(password.as_str())
+                .server_address(self.server_address.as_deref().unwrap_or(registry))
+                .build())
+        } else {
+            Err(format_err!(
+                "Credentials for registry {} must have a username and password or an identity \
+                 token",
+                registry
+            ))
+        }
+    }
+}
+
+/// The HTTP proxy settings of the Juju model
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub(crate) struct ProxySettings {
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    #[serde(default)]
+    pub https_proxy: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    /// The hook environment variables that the proxy settings are read from
+    ///
+    /// The `JUJU_CHARM_*` variables are set from the model's `juju-*-proxy` settings and are
+    /// preferred over the legacy variables set from the model's `*-proxy` settings.
+    pub const ENV_VARS: &'static [&'static str] = &[
+        "JUJU_CHARM_HTTP_PROXY",
+        "JUJU_CHARM_HTTPS_PROXY",
+        "JUJU_CHARM_NO_PROXY",
+        "HTTP_PROXY",
+        "HTTPS_PROXY",
+        "NO_PROXY",
+    ];
+
+    /// Get the proxy settings from a hook environment
+    pub fn from_env(environment: &HashMap<String, String>) -> Self {
+        let get = |vars: &[&str]| {
+            vars.iter()
+                .filter_map(|&var| environment.get(var))
+                .find(|value| !value.is_empty())
+                .cloned()
+        };
+
+        ProxySettings {
+            http_proxy: get(&["JUJU_CHARM_HTTP_PROXY", "HTTP_PROXY"]),
+            https_proxy: get(&["JUJU_CHARM_HTTPS_PROXY", "HTTPS_PROXY"]),
+            no_proxy: get(&["JUJU_CHARM_NO_PROXY", "NO_PROXY"]),
+        }
+    }
+
+    /// Get the proxy environment variables, in both upper and lower case, for the proxy settings
+    /// that are set
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let mut vars = Vec::new();
+        for (var, value) in &[
+            ("HTTP_PROXY", &self.http_proxy),
+            ("HTTPS_PROXY", &self.https_proxy),
+            ("NO_PROXY", &self.no_proxy),
+        ] {
+            if let Some(value) = value {
+                vars.push((var.to_string(), value.clone()));
+                vars.push((var.to_lowercase(), value.clone()));
+            }
+        }
+
+        vars
+    }
+}
+
+/// Get the repository of an image by stripping its tag and digest
+///
+/// For example, the repository of `localhost:5000/app:1.0` is `localhost:5000/app`.
+pub(crate) fn image_repository(image: &str) -> &str {
+    // Strip the digest
+    let image = image.split('@').next().unwrap_or(image);
+
+    // Strip the tag, which is after the last `:` that comes after the last `/`
+    let name_start = image.rfind('/').map_or(0, |i| i + 1);
+    match image[name_start..].rfind(':') {
+        Some(i) => &image[..name_start + i],
+        None => image,
+    }
+}
+
+/// Get the registry that an image will be pulled from
+///
+/// Images without a registry in their name are pulled from `docker.io`.
+pub(crate) fn image_registry(image: &str) -> &str {
+    match image.find('/') {
+        Some(index) => {
+            let first = &image[..index];
+            if first.contains('.') || first.contains(':') || first == "localhost" {
+                first
+            } else {
+                "docker.io"
+            }
+        }
+        None => "docker.io",
+    }
+}
+
+/// An image built from a Dockerfile in the charm's `container_images` dir
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct ImageBuild {
+    /// The name of the image's dir in `container_images`
+    pub name: String,
+    /// The tag that the image was built with
+    pub tag: String,
+    /// The hash of the build context, used to detect when the image needs to be rebuilt
+    pub context_hash: String,
+    /// The ID of the built image
+    pub image_id: String,
+}
+
+/// An image that one of the unit's containers has been run with
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub(crate) struct TrackedImage {
+    /// The image reference that the container was run with, such as `nginx:latest`
+    pub image: String,
+    /// The ID of the image
+    pub id: String,
 }
 
 #[derive(Shrinkwrap, Serialize, Deserialize, PartialEq, Eq, Hash, Default, Clone, Debug)]
@@ -53,20 +375,223 @@ pub struct VolumeSource(pub String);
 /// A volume target path wrapper type to make it more difficult to mix-up sources and targets
 pub struct VolumeTarget(pub String);
 
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    AsRefStr,
+    EnumString,
+    EnumVariantNames,
+    Serialize,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+/// The type of a container mount
+pub(crate) enum MountType {
+    /// A host path or a named volume in the unit's Lucky data dir
+    Bind,
+    /// A Docker named volume
+    Volume,
+    /// A tmpfs mount
+    Tmpfs,
+}
+
+impl Default for MountType {
+    fn default() -> Self {
+        MountType::Bind
+    }
+}
+
+/// Options for a container mount
+///
+/// Volumes that don't have any mount options are bind mounted read-write.
+#[derive(Serialize, Deserialize, Default, PartialEq, Clone, Debug)]
+pub(crate) struct MountOptions {
+    #[serde(default)]
+    pub mount_type: MountType,
+    #[serde(default)]
+    pub read_only: bool,
+    /// The bind propagation mode, such as `rslave`, for bind mounts
+    #[serde(default)]
+    pub propagation: Option<String>,
+    /// The volume driver for Docker named volumes
+    #[serde(default)]
+    pub driver: Option<String>,
+    /// The volume driver options for Docker named volumes
+    #[serde(default)]
+    pub driver_options: HashMap<String, String>,
+    /// The size limit of tmpfs mounts in bytes
+    #[serde(default)]
+    pub tmpfs_size: Option<i64>,
+}
+
+impl TryFrom<rpc::MountOptions> for MountOptions {
+    type Error = anyhow::Error;
+
+    fn try_from(options: rpc::MountOptions) -> anyhow::Result<Self> {
+        Ok(MountOptions {
+            mount_type: match options.mount_type {
+                Some(mount_type) => mount_type
+                    .parse()
+                    .context(format!("Invalid mount type: {}", mount_type))?,
+                None => MountType::default(),
+            },
+            read_only: options.read_only,
+            propagation: options.propagation,
+            driver: options.driver,
+            driver_options: options.driver_options,
+            tmpfs_size: match options.tmpfs_size {
+                Some(size) => Some(parse_byte_size(&size)?),
+                None => None,
+            },
+        })
+    }
+}
+
+impl From<MountOptions> for rpc::MountOptions {
+    fn from(options: MountOptions) -> Self {
+        rpc::MountOptions {
+            mount_type: Some(options.mount_type.as_ref().into()),
+            read_only: options.read_only,
+            propagation: options.propagation,
+            driver: options.driver,
+            driver_options: options.driver_options,
+            tmpfs_size: options.tmpfs_size.map(|size| size.to_string()),
+        }
+    }
+}
+
+/// Get the name of the Docker volume used for a Lucky volume of type `MountType::Volume`
+///
+/// Docker volume names are prefixed with the unit name so that units on the same host don't share
+/// volumes.
+pub(crate) fn docker_volume_name(unit_name: &str, volume_name: &str) -> String {
+    format!("lucky_{}_{}", unit_name.replace("/", "_"), volume_name)
+}
+
+/// A binding of a container port, or range of ports, to a host port
+///
+/// A `host_port` of `0` means that a free host port will be allocated when the container is
+/// deployed. The allocated port is kept in the container's config.
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Default, Clone, Debug)]
 pub struct PortBinding {
-    pub container_port: u32,
+    /// The host IP address to bind to. Binds to all of the host's addresses if `None`.
+    #[serde(default)]
+    pub host_ip: Option<String>,
     pub host_port: u32,
+    /// The last host port of a port range
+    #[serde(default)]
+    pub host_port_end: Option<u32>,
+    pub container_port: u32,
+    /// The last container port of a port range
+    #[serde(default)]
+    pub container_port_end: Option<u32>,
     pub protocol: String,
 }
 
+impl PortBinding {
+    /// Get the number of ports that are bound by this port binding
+    pub fn port_count(&self) -> u32 {
+        self.container_port_end
+            .map_or(1, |end| end - self.container_port + 1)
+    }
+
+    /// Returns `true` if this port binding binds any of the same host or container ports as the
+    /// `other` port binding. The host ports of the port bindings are given separately so that
+    /// allocated host ports can be used.
+    pub fn conflicts_with(
+        &self,
+        host_port: u32,
+        other: &PortBinding,
+        other_host_port: u32,
+    ) -> bool {
+        let overlaps = |start: u32, other_start: u32| {
+            start < other_start + other.port_count() && other_start < start + self.port_count()
+        };
+        // Port bindings to different host IPs don't conflict with each other
+        let same_host_ip =
+            self.host_ip.is_none() || other.host_ip.is_none() || self.host_ip == other.host_ip;
+
+        self.protocol == other.protocol
+            && same_host_ip
+            && (
+                // Host ports that will be allocated don't conflict
+                (host_port != 0 && other_host_port != 0 && overlaps(host_port, other_host_port))
+                    || overlaps(self.container_port, other.container_port)
+            )
+    }
+
+    /// Make sure that the port binding is valid
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(host_ip) = &self.host_ip {
+            host_ip
+                .parse::<IpAddr>()
+                .context(format!("Invalid host IP address: {}", host_ip))?;
+        }
+
+        for &port in [
+            Some(self.host_port),
+            self.host_port_end,
+            Some(self.container_port),
+            self.container_port_end,
+        ]
+        .iter()
+        .flatten()
+        {
+            if port > 65535 {
+                bail!("Invalid port number: {}", port);
+            }
+        }
+        if self.container_port == 0 {
+            bail!("The container port must not be 0");
+        }
+
+        match (self.host_port_end, self.container_port_end) {
+            (None, None) => (),
+            (Some(host_port_end), Some(container_port_end)) => {
+                if self.host_port == 0 {
+                    bail!("Host ports can't be allocated for port ranges");
+                }
+                if host_port_end < self.host_port || container_port_end < self.container_port {
+                    bail!("The end of a port range must not be less than its start");
+                }
+                if host_port_end - self.host_port != container_port_end - self.container_port {
+                    bail!("The host and container port ranges must be the same size");
+                }
+            }
+            _ => bail!("The host and container port ranges must be the same size"),
+        }
+
+        if self.protocol != "tcp" && self.protocol != "udp" {
+            bail!("Invalid protocol: {}", self.protocol);
+        }
+
+        Ok(())
+    }
+}
+
 impl std::fmt::Display for PortBinding {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}:{}/{}",
-            self.host_port, self.container_port, self.protocol
-        )
+        // Put IPv6 addresses in brackets to separate them from the ports
+        match &self.host_ip {
+            Some(host_ip) if host_ip.contains(':') => write!(f, "[{}]:", host_ip)?,
+            Some(host_ip) => write!(f, "{}:", host_ip)?,
+            None => (),
+        }
+
+        write!(f, "{}", self.host_port)?;
+        if let Some(host_port_end) = self.host_port_end {
+            write!(f, "-{}", host_port_end)?;
+        }
+        write!(f, ":{}", self.container_port)?;
+        if let Some(container_port_end) = self.container_port_end {
+            write!(f, "-{}", container_port_end)?;
+        }
+
+        write!(f, "/{}", self.protocol)
     }
 }
 
@@ -74,34 +599,89 @@ impl FromStr for PortBinding {
     type Err = anyhow::Error;
 
     fn from_str(port_string: &str) -> Result<Self, Self::Err> {
-        let re = Regex::new(
-            r"^(?P<host_port>[0-9]{1,5}):(?P<container_port>[0-9]{1,5})(/(?P<protocol>(tcp|udp)))?$",
-        ).expect("Could not compile regex");
+        let re = Regex::new(concat!(
+            r"^((?P<host_ip>[0-9.]+|\[[0-9a-fA-F:.]+\]):)?",
+            r"(?P<host_port>[0-9]{1,5}|auto)(-(?P<host_port_end>[0-9]{1,5}))?",
+            r":(?P<container_port>[0-9]{1,5})(-(?P<container_port_end>[0-9]{1,5}))?",
+            r"(/(?P<protocol>(tcp|udp)))?$",
+        ))
+        .expect("Could not compile regex");
 
         if let Some(captures) = re.captures(port_string) {
-            Ok(PortBinding {
-                host_port: captures
+            let port = |name| {
+                captures
+                    .name(name)
+                    .map(|x| x.as_str().parse().expect("Could not parse int"))
+            };
+
+            let port_binding = PortBinding {
+                host_ip: captures.name("host_ip").map(|x| {
+                    x.as_str()
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .into()
+                }),
+                // A host port of `auto` is the same as `0`, which means that a host port will be
+                // allocated when the container is deployed
+                host_port: match captures
                     .name("host_port")
                     .expect("Expected host port")
                     .as_str()
-                    .parse()
-                    .expect("Could not parse int"),
-                container_port: captures
-                    .name("container_port")
-                    .expect("expected container port")
-                    .as_str()
-                    .parse()
-                    .expect("Could not parse int"),
+                {
+                    "auto" => 0,
+                    port => port.parse().expect("Could not parse int"),
+                },
+                host_port_end: port("host_port_end"),
+                container_port: port("container_port").expect("Expected container port"),
+                container_port_end: port("container_port_end"),
                 protocol: captures
                     .name("protocol")
                     .map_or("tcp".into(), |x| x.as_str().into()),
-            })
+            };
+            port_binding
+                .validate()
+                .context(format!("Invalid port binding: {}", port_string))?;
+
+            Ok(port_binding)
         } else {
             Err(anyhow::format_err!("Could not parse port binding"))
         }
     }
 }
 
+impl TryFrom<rpc::PortBinding> for PortBinding {
+    type Error = anyhow::Error;
+
+    fn try_from(binding: rpc::PortBinding) -> anyhow::Result<Self> {
+        let port = |port: i64| u32::try_from(port).context("Invalid port number");
+
+        let port_binding = PortBinding {
+            host_ip: binding.host_ip,
+            host_port: port(binding.host_port)?,
+            host_port_end: binding.host_port_end.map(port).transpose()?,
+            container_port: port(binding.container_port)?,
+            container_port_end: binding.container_port_end.map(port).transpose()?,
+            protocol: binding.protocol,
+        };
+        port_binding.validate()?;
+
+        Ok(port_binding)
+    }
+}
+
+impl From<PortBinding> for rpc::PortBinding {
+    fn from(binding: PortBinding) -> Self {
+        rpc::PortBinding {
+            host_ip: binding.host_ip,
+            host_port: binding.host_port.into(),
+            host_port_end: binding.host_port_end.map(Into::into),
+            container_port: binding.container_port.into(),
+            container_port_end: binding.container_port_end.map(Into::into),
+            protocol: binding.protocol,
+        }
+    }
+}
+
 /// The container configuration options such as image, volumes, ports, etc.
 #[derive(Serialize, Deserialize, Default, PartialEq, Clone, Debug)]
 pub(crate) struct ContainerConfig {
@@ -111,9 +691,47 @@ pub(crate) struct ContainerConfig {
     pub command: Option<Vec<String>>,
     /// Volume mapping from target to source
     pub volumes: HashMap<VolumeTarget, VolumeSource>,
+    /// Mount options for the volumes that have them
+    #[serde(default)]
+    pub mount_options: HashMap<VolumeTarget, MountOptions>,
     // The port bindings
     pub ports: HashSet<PortBinding>,
+    /// The host ports that have been allocated for the port bindings with a host port of `0`,
+    /// keyed by the port binding
+    #[serde(default)]
+    pub allocated_host_ports: HashMap<String, u32>,
     pub network: Option<String>,
+    /// The container's resource limits
+    #[serde(default)]
+    pub limits: ResourceLimits,
+    /// The user to run the container as, in the form `user[:group]`
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The working directory for commands run in the container
+    #[serde(default)]
+    pub working_dir: Option<String>,
+    /// The container hostname
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// Custom container labels. Labels starting with `lucky.` are reserved for Lucky.
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Linux capabilities to add to the container, such as `NET_ADMIN`
+    #[serde(default)]
+    pub cap_add: HashSet<String>,
+    /// Whether or not to run the container in privileged mode
+    #[serde(default)]
+    pub privileged: bool,
+    /// The size of `/dev/shm` in bytes
+    #[serde(default)]
+    pub shm_size: Option<i64>,
+    /// Extra entries for the container's `/etc/hosts` file, mapping hostname to IP address
+    #[serde(default)]
+    pub extra_hosts: HashMap<String, String>,
+    /// The Lucky networks that the container is attached to, mapped to the container's aliases on
+    /// that network
+    #[serde(default)]
+    pub networks: HashMap<String, HashSet<String>>,
 }
 
 impl ContainerConfig {
@@ -124,16 +742,53 @@ impl ContainerConfig {
         }
     }
 
-    /// Get a `ContainerOptions` struct that can be given to shiplift to run the container
+    /// Get the host port that a port binding is bound to
+    ///
+    /// This is the allocated host port for port bindings with a host port of `0`, or `0` if a host
+    /// port has not been allocated yet.
+    pub fn host_port(&self, binding: &PortBinding) -> u32 {
+        if binding.host_port == 0 {
+            self.allocated_host_ports
+                .get(&binding.to_string())
+                .copied()
+                .unwrap_or(0)
+        } else {
+            binding.host_port
+        }
+    }
+
+    /// Get a port binding that conflicts with the given port binding, if any
+    ///
+    /// Port bindings conflict if they bind any of the same host or container ports with the same
+    /// protocol.
+    pub fn conflicting_port_binding(&self, port_binding: &PortBinding) -> Option<&PortBinding> {
+        self.ports.iter().find(|&binding| {
+            binding != port_binding
+                && port_binding.conflicts_with(
+                    port_binding.host_port,
+                    binding,
+                    self.host_port(binding),
+                )
+        })
+    }
+
+    /// Get the `ContainerCreateOptions` that can be used to create the container
     ///
     /// The `charm_dir` is used as reference when mounting the container scripts into the container
-    /// and the `socket_path` is used to mount the Lucky Daemon socket inside the container.
+    /// and the `socket_path` is used to mount the Lucky Daemon socket inside the container. The
+    /// `unit_name` and `container_name` are used to label the container so that it can be
+    /// identified as managed by Lucky. A `container_name` of `None` is the default container. Any
+    /// `proxy_settings` are added to the container's environment unless the container's own
+    /// environment sets the same variables.
     pub fn to_container_options(
         &self,
         charm_dir: &Path,
         lucky_data_dir: &Path,
         socket_path: &Path,
-    ) -> anyhow::Result<ContainerOptions> {
+        unit_name: &str,
+        container_name: Option<&str>,
+        proxy_settings: Option<&ProxySettings>,
+    ) -> anyhow::Result<ContainerCreateOptions> {
         let mut options = ContainerOptions::builder(&self.image);
         let mut volumes: Vec<String> = vec![];
         let mut env: Vec<String> = vec![];
@@ -166,6 +821,15 @@ impl ContainerConfig {
         // Set lucky context to client
         env.push("LUCKY_CONTEXT=client".into());
 
+        // Add the proxy settings
+        if let Some(proxy_settings) = proxy_settings {
+            for (var, value) in proxy_settings.env_vars() {
+                if !self.env_vars.contains_key(&var) {
+                    env.push(format!("{}={}", var, value));
+                }
+            }
+        }
+
         // Add the rest of the environment variables
         for (var, value) in &self.env_vars {
             env.push(format!("{}={}", var, value));
@@ -182,33 +846,77 @@ impl ContainerConfig {
         }
 
         // Add other specified volumes
+        let mut mounts: Vec<JsonValue> = vec![];
         for (target, source) in &self.volumes {
-            let host_path = if source.starts_with('/') {
-                PathBuf::from(&**source)
-            } else {
-                lucky_data_dir.join(VOLUME_DIR).join(&**source)
-            };
+            let mount_options = self.mount_options.get(target).cloned();
+            let mount_type = mount_options
+                .as_ref()
+                .map_or(MountType::Bind, |options| options.mount_type);
 
-            // Create the host path
-            if !host_path.exists() {
-                fs::create_dir_all(&host_path).context(format!(
-                    "Could not create dir: {}",
-                    host_path.to_string_lossy()
-                ))?;
-            }
+            match mount_type {
+                MountType::Bind => {
+                    let host_path = if source.starts_with('/') {
+                        PathBuf::from(&**source)
+                    } else {
+                        lucky_data_dir.join(VOLUME_DIR).join(&**source)
+                    };
 
-            // Add volume to container
-            volumes.push(format!("{}:{}", host_path.to_string_lossy(), &**target));
-        }
+                    // Create the host path
+                    if !host_path.exists() {
+                        fs::create_dir_all(&host_path).context(format!(
+                            "Could not create dir: {}",
+                            host_path.to_string_lossy()
+                        ))?;
+                    }
 
-        // Add ports
-        for PortBinding {
-            container_port,
-            protocol,
-            host_port,
-        } in &self.ports
-        {
-            options.expose(*container_port, protocol, *host_port);
+                    if let Some(options) = mount_options {
+                        // Add bind mount with options to container
+                        let mut mount = json!({
+                            "Type": "bind",
+                            "Source": host_path,
+                            "Target": &**target,
+                            "ReadOnly": options.read_only,
+                        });
+                        if let Some(propagation) = &options.propagation {
+                            mount["BindOptions"] = json!({ "Propagation": propagation });
+                        }
+                        mounts.push(mount);
+                    } else {
+                        // Add volume to container
+                        volumes.push(format!("{}:{}", host_path.to_string_lossy(), &**target));
+                    }
+                }
+                MountType::Volume => {
+                    let options = mount_options.unwrap_or_default();
+                    let mut mount = json!({
+                        "Type": "volume",
+                        "Source": docker_volume_name(unit_name, source),
+                        "Target": &**target,
+                        "ReadOnly": options.read_only,
+                    });
+                    if options.driver.is_some() || !options.driver_options.is_empty() {
+                        mount["VolumeOptions"] = json!({
+                            "DriverConfig": {
+                                "Name": options.driver.as_deref().unwrap_or("local"),
+                                "Options": options.driver_options,
+                            }
+                        });
+                    }
+                    mounts.push(mount);
+                }
+                MountType::Tmpfs => {
+                    let options = mount_options.unwrap_or_default();
+                    let mut mount = json!({
+                        "Type": "tmpfs",
+                        "Target": &**target,
+                        "ReadOnly": options.read_only,
+                    });
+                    if let Some(size) = options.tmpfs_size {
+                        mount["TmpfsOptions"] = json!({ "SizeBytes": size });
+                    }
+                    mounts.push(mount);
+                }
+            }
         }
 
         // Set network
@@ -226,72 +934,472 @@ impl ContainerConfig {
         options.restart_policy("unless-stopped", 0 /* Maximum retry count */);
 
         // Build options
-        Ok(options.build())
+        let options = options.build();
+        let mut body: JsonValue = serde_json::from_str(&options.serialize()?)
+            .context("Could not serialize container options")?;
+
+        // TODO: Shiplift's options builder doesn't support setting any of the following options so
+        // we add them to the request body ourselves. We should remove this when shiplift catches
+        // up.
+
+        // Add resource limits
+        self.limits.add_to_host_config(&mut body["HostConfig"]);
+
+        // Add the port bindings. Shiplift can't set the host IP or bind port ranges.
+        let mut exposed_ports = serde_json::Map::new();
+        let mut port_bindings = serde_json::Map::new();
+        for binding in &self.ports {
+            let host_port = self.host_port(binding);
+            for i in 0..binding.port_count() {
+                let container_port = format!("{}/{}", binding.container_port + i, binding.protocol);
+                exposed_ports.insert(container_port.clone(), json!({}));
+                let host_bindings = port_bindings
+                    .entry(container_port)
+                    .or_insert_with(|| json!([]));
+                if let JsonValue::Array(host_bindings) = host_bindings {
+                    host_bindings.push(json!({
+                        "HostIp": binding.host_ip.as_deref().unwrap_or(""),
+                        // An empty host port lets Docker pick the port
+                        "HostPort": if host_port == 0 {
+                            "".into()
+                        } else {
+                            (host_port + i).to_string()
+                        },
+                    }));
+                }
+            }
+        }
+        if !port_bindings.is_empty() {
+            body["ExposedPorts"] = JsonValue::Object(exposed_ports);
+            body["HostConfig"]["PortBindings"] = JsonValue::Object(port_bindings);
+        }
+
+        // Add mounts that can't be expressed as volume strings
+        if !mounts.is_empty() {
+            body["HostConfig"]["Mounts"] = json!(mounts);
+        }
+
+        // Add the user, working dir, and hostname
+        if let Some(user) = &self.user {
+            body["User"] = json!(user);
+        }
+        if let Some(working_dir) = &self.working_dir {
+            body["WorkingDir"] = json!(working_dir);
+        }
+        if let Some(hostname) = &self.hostname {
+            body["Hostname"] = json!(hostname);
+        }
+
+        // Add labels, including the labels used to identify Lucky's containers
+        let mut labels = self.labels.clone();
+        labels.insert(LUCKY_UNIT_LABEL.into(), unit_name.into());
+        labels.insert(
+            LUCKY_CONTAINER_LABEL.into(),
+            container_name.unwrap_or(DEFAULT_CONTAINER_NAME).into(),
+        );
+        body["Labels"] = json!(labels);
+
+        // Add capabilities and privileged mode
+        if !self.cap_add.is_empty() {
+            body["HostConfig"]["CapAdd"] = json!(self.cap_add);
+        }
+        if self.privileged {
+            body["HostConfig"]["Privileged"] = json!(true);
+        }
+
+        // Add shared memory size
+        if let Some(shm_size) = self.shm_size {
+            body["HostConfig"]["ShmSize"] = json!(shm_size);
+        }
+
+        // Add extra hosts
+        if !self.extra_hosts.is_empty() {
+            body["HostConfig"]["ExtraHosts"] = self
+                .extra_hosts
+                .iter()
+                .map(|(hostname, ip)| json!(format!("{}:{}", hostname, ip)))
+                .collect();
+        }
+
+        // Attach the container to its Lucky networks. Named containers can be reached on the
+        // network by their Lucky name.
+        let mut network_connections = Vec::new();
+        let mut networks: Vec<_> = self.networks.iter().collect();
+        networks.sort_by_key(|(name, _)| name.as_str());
+        for (network_name, aliases) in networks {
+            let mut aliases: Vec<String> = aliases.iter().cloned().collect();
+            if let Some(container_name) = container_name {
+                aliases.push(container_name.into());
+            }
+            aliases.sort();
+            aliases.dedup();
+
+            network_connections.push(NetworkConnection {
+                network: docker_network_name(unit_name, network_name),
+                aliases,
+            });
+        }
+        // If the network mode hasn't been set, use the first network as the network mode so that
+        // the container is not also attached to the default bridge network
+        if self.network.is_none() && !network_connections.is_empty() {
+            let connection = network_connections.remove(0);
+            body["HostConfig"]["NetworkMode"] = json!(&connection.network);
+            body["NetworkingConfig"]["EndpointsConfig"][&connection.network] =
+                json!({ "Aliases": connection.aliases });
+        }
+
+        Ok(ContainerCreateOptions {
+            name: options.name,
+            body,
+            network_connections,
+        })
+    }
+}
+
+/// The options used to create a container with the Docker API
+#[derive(Clone, Debug)]
+pub(crate) struct ContainerCreateOptions {
+    /// The name of the container
+    pub name: Option<String>,
+    /// The JSON body of the container create request
+    pub body: JsonValue,
+    /// Networks to connect the container to after it has been created
+    pub network_connections: Vec<NetworkConnection>,
+}
+
+/// A connection from a container to a Docker network
+#[derive(Clone, Debug)]
+pub(crate) struct NetworkConnection {
+    /// The Docker name of the network
+    pub network: String,
+    /// The container's aliases on the network
+    pub aliases: Vec<String>,
+}
+
+/// Get the Docker name of a Lucky network
+///
+/// Docker network names are prefixed with the unit name so that units on the same host don't
+/// share networks.
+pub(crate) fn docker_network_name(unit_name: &str, network_name: &str) -> String {
+    format!("lucky_{}_{}", unit_name.replace("/", "_"), network_name)
+}
+
+/// A ulimit setting for a container
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub(crate) struct Ulimit {
+    pub soft: i64,
+    pub hard: i64,
+}
+
+impl fmt::Display for Ulimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.soft, self.hard)
+    }
+}
+
+impl FromStr for Ulimit {
+    type Err = anyhow::Error;
+
+    /// Parse a ulimit in the form `soft:hard` or just `limit` to set both the soft and the hard
+    /// limit to the same value.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let soft = parts
+            .next()
+            .unwrap_or("")
+            .parse()
+            .context(format!("Invalid ulimit: {}", s))?;
+        let hard = match parts.next() {
+            Some(hard) => hard.parse().context(format!("Invalid ulimit: {}", s))?,
+            None => soft,
+        };
+
+        Ok(Ulimit { soft, hard })
+    }
+}
+
+/// Resource limits for a container
+///
+/// Unset limits are left up to Docker's defaults.
+#[derive(Serialize, Deserialize, Default, PartialEq, Clone, Debug)]
+pub(crate) struct ResourceLimits {
+    /// The memory limit in bytes
+    pub memory: Option<i64>,
+    /// The total memory + swap limit in bytes. `-1` allows unlimited swap.
+    pub memory_swap: Option<i64>,
+    /// The relative CPU weight of the container compared to other containers
+    pub cpu_shares: Option<i64>,
+    /// The length of the CPU CFS period in microseconds
+    pub cpu_period: Option<i64>,
+    /// The CPU time that the container may use per CFS period in microseconds
+    pub cpu_quota: Option<i64>,
+    /// The CPUs that the container is allowed to run on, such as `0-3` or `0,1`
+    pub cpuset_cpus: Option<String>,
+    /// The maximum number of processes in the container. `-1` means unlimited.
+    pub pids_limit: Option<i64>,
+    /// The container ulimits by name, such as `nofile`
+    pub ulimits: HashMap<String, Ulimit>,
+}
+
+impl ResourceLimits {
+    /// Set a limit by its name. Setting a limit to `None` will remove it.
+    ///
+    /// Ulimits are named `ulimit-[name]`, for example `ulimit-nofile`.
+    pub fn set(&mut self, name: &str, value: Option<&str>) -> anyhow::Result<()> {
+        // Parse an integer limit value
+        fn int(name: &str, value: Option<&str>) -> anyhow::Result<Option<i64>> {
+            value
+                .map(|v| {
+                    v.parse()
+                        .context(format!("Invalid value for {}: {}", name, v))
+                })
+                .transpose()
+        }
+        // Parse a byte size limit value
+        fn bytes(name: &str, value: Option<&str>) -> anyhow::Result<Option<i64>> {
+            value
+                .map(|v| parse_byte_size(v).context(format!("Invalid value for {}: {}", name, v)))
+                .transpose()
+        }
+
+        match name {
+            "memory" => self.memory = bytes(name, value)?,
+            "memory-swap" => {
+                self.memory_swap = if value == Some("-1") {
+                    Some(-1)
+                } else {
+                    bytes(name, value)?
+                }
+            }
+            "cpu-shares" => self.cpu_shares = int(name, value)?,
+            "cpu-period" => self.cpu_period = int(name, value)?,
+            "cpu-quota" => self.cpu_quota = int(name, value)?,
+            "cpuset-cpus" => self.cpuset_cpus = value.map(ToOwned::to_owned),
+            "pids-limit" => self.pids_limit = int(name, value)?,
+            other if other.starts_with("ulimit-") => {
+                let ulimit_name = other.trim_start_matches("ulimit-");
+                if ulimit_name.is_empty() {
+                    bail!("Ulimit name cannot be empty");
+                }
+
+                if let Some(value) = value {
+                    self.ulimits
+                        .insert(ulimit_name.into(), value.parse::<Ulimit>()?);
+                } else {
+                    self.ulimits.remove(ulimit_name);
+                }
+            }
+            other => bail!("Unknown resource limit: {}", other),
+        }
+
+        Ok(())
+    }
+
+    /// Get the name and value of every limit that has been set
+    pub fn get_all(&self) -> Vec<(String, String)> {
+        let mut limits = vec![];
+
+        let int_limits = [
+            ("memory", self.memory),
+            ("memory-swap", self.memory_swap),
+            ("cpu-shares", self.cpu_shares),
+            ("cpu-period", self.cpu_period),
+            ("cpu-quota", self.cpu_quota),
+            ("pids-limit", self.pids_limit),
+        ];
+        for (name, value) in int_limits.iter() {
+            if let Some(value) = value {
+                limits.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        if let Some(cpuset_cpus) = &self.cpuset_cpus {
+            limits.push(("cpuset-cpus".into(), cpuset_cpus.clone()));
+        }
+
+        for (name, ulimit) in &self.ulimits {
+            limits.push((format!("ulimit-{}", name), ulimit.to_string()));
+        }
+
+        limits
+    }
+
+    /// Add the limits to the `HostConfig` section of a container create request body
+    fn add_to_host_config(&self, host_config: &mut JsonValue) {
+        let int_limits = [
+            ("Memory", self.memory),
+            ("MemorySwap", self.memory_swap),
+            ("CpuShares", self.cpu_shares),
+            ("CpuPeriod", self.cpu_period),
+            ("CpuQuota", self.cpu_quota),
+            ("PidsLimit", self.pids_limit),
+        ];
+        for (key, value) in int_limits.iter() {
+            if let Some(value) = value {
+                host_config[*key] = json!(value);
+            }
+        }
+
+        if let Some(cpuset_cpus) = &self.cpuset_cpus {
+            host_config["CpusetCpus"] = json!(cpuset_cpus);
+        }
+
+        if !self.ulimits.is_empty() {
+            host_config["Ulimits"] = self
+                .ulimits
+                .iter()
+                .map(|(name, ulimit)| json!({ "Name": name, "Soft": ulimit.soft, "Hard": ulimit.hard }))
+                .collect();
+        }
     }
 }
 
-/// Make sure Docker is installed an available
-pub(crate) fn ensure_docker() -> anyhow::Result<()> {
-    // Skip if docker is already installed
-    if cmd_exists("docker", &["--version"])? {
-        return Ok(());
+/// Parse a byte size such as `512m` or `2g` into a number of bytes
+///
+/// Supported suffixes are `b`, `k`, `m`, and `g` ( case insensitive ). Numbers without a suffix are
+/// interpreted as bytes.
+pub(crate) fn parse_byte_size(size: &str) -> anyhow::Result<i64> {
+    let size = size.trim().to_lowercase();
+    let (number, multiplier) = match size.chars().last() {
+        Some('b') => (&size[..size.len() - 1], 1),
+        Some('k') => (&size[..size.len() - 1], 1024),
+        Some('m') => (&size[..size.len() - 1], 1024 * 1024),
+        Some('g') => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (&size[..], 1),
     };
 
-    // Install Docker
-    // TODO: We need to figure out whether or not to use the Docker snap, which doesn't work on
-    // LXD. We will also want to support Centos, but we might just have to install different
-    // packages depending on the system.
-    run_cmd_with_retries(
-        "apt-get",
-        &["install", "-y", "docker.io"],
-        &Default::default(),
-    )?;
-    // Make sure docker is installed
-    if !cmd_exists("docker", &["--version"])? {
-        bail!("Could not install Docker");
-    }
-
-    // Get proxy settings from environment
-    let mut proxy_settings = String::new();
-    if let Ok(http_proxy) = std::env::var("HTTP_PROXY").or_else(|_| std::env::var("http_proxy")) {
-        proxy_settings.push_str(&format!("Environment=\"HTTP_PROXY={}\"\n", http_proxy));
-    }
-    if let Ok(https_proxy) = std::env::var("HTTPS_PROXY").or_else(|_| std::env::var("https_proxy"))
-    {
-        proxy_settings.push_str(&format!("Environment=\"HTTPS_PROXY={}\"\n", https_proxy));
-    }
-
-    // If there are any proxy settings
-    if proxy_settings != "" {
-        // Create the Docker service drop-in dir
-        let dropin_dir = "/etc/systemd/system/docker.service.d/";
-        fs::create_dir_all(dropin_dir).context(format!(
-            "Could not create docker service drop-in config dir: {:?}",
-            dropin_dir
-        ))?;
-
-        // Open the drop-in file
-        let file_path = "/etc/systemd/system/docker.service.d/http-proxy.conf";
-        let mut file = fs::OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(file_path)
-            .context(format!(
-                "Could not open Docker service dropin file: {:?}",
-                file_path
-            ))?;
-
-        // Insert proxy settings
-        file.write_all(format!("[Service]\n{}", proxy_settings).as_bytes())?;
-
-        // Close file
-        drop(file);
-
-        // Reload Docker config
-        run_cmd("systemctl", &["daemon-reload"])?;
-        run_cmd("systemctl", &["restart", "docker"])?;
-    }
-
-    Ok(())
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format_err!("Could not parse byte size: {}", size))?;
+
+    if number < 0. {
+        bail!("Byte size cannot be negative: {}", size);
+    }
+
+    Ok((number * multiplier as f64) as i64)
+}
+
+/// Get the tag that an image built from the charm's `container_images` dir will be given if a tag
+/// is not specified
+pub(crate) fn default_image_tag(unit_name: &str, image_name: &str) -> String {
+    format!(
+        "lucky/{}/{}:latest",
+        unit_name.replace("/", "_"),
+        image_name
+    )
+    .to_lowercase()
+}
+
+/// Hash the contents of a Docker build context dir
+///
+/// The hash is used to detect changes to the build context so that images are only rebuilt when
+/// necessary.
+pub(crate) fn hash_build_context(context_dir: &Path) -> anyhow::Result<String> {
+    // Use SHA-256 because the hash is stored in the daemon state and must be the same for every
+    // build of Lucky
+    let mut hasher = Sha256::new();
+
+    // Hash the path and contents of every file in the context in a deterministic order
+    for entry in WalkDir::new(context_dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry?;
+        let relative_path = entry
+            .path()
+            .strip_prefix(context_dir)
+            .expect("Internal error parsing build context paths");
+
+        // Prefix each path and file with its length so that entries can't run together
+        let path = relative_path.to_string_lossy();
+        hasher.input(&(path.len() as u64).to_le_bytes());
+        hasher.input(path.as_bytes());
+
+        if entry.file_type().is_file() {
+            let contents = fs::read(entry.path())
+                .context(format!("Could not read file: {:?}", entry.path()))?;
+            hasher.input(&(contents.len() as u64).to_le_bytes());
+            hasher.input(&contents);
+        }
+    }
+
+    Ok(format!("{:x}", hasher.result()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a port binding, format it, and parse it again, making sure nothing was lost
+    fn round_trip(port_string: &str) -> PortBinding {
+        let binding: PortBinding = port_string.parse().expect("Could not parse port binding");
+        let reparsed: PortBinding = binding
+            .to_string()
+            .parse()
+            .expect("Could not parse formatted port binding");
+        assert_eq!(binding, reparsed);
+
+        binding
+    }
+
+    #[test]
+    fn port_binding_round_trips_ipv4_host_ip() {
+        let binding = round_trip("127.0.0.1:8080:80/udp");
+        assert_eq!(binding.host_ip.as_deref(), Some("127.0.0.1"));
+        assert_eq!(binding.host_port, 8080);
+        assert_eq!(binding.container_port, 80);
+        assert_eq!(binding.protocol, "udp");
+        assert_eq!(binding.to_string(), "127.0.0.1:8080:80/udp");
+    }
+
+    #[test]
+    fn port_binding_round_trips_ipv6_host_ip() {
+        let binding = round_trip("[::1]:8080:80");
+        assert_eq!(binding.host_ip.as_deref(), Some("::1"));
+        assert_eq!(binding.protocol, "tcp");
+        assert_eq!(binding.to_string(), "[::1]:8080:80/tcp");
+    }
+
+    #[test]
+    fn port_binding_round_trips_port_ranges() {
+        let binding = round_trip("8000-8010:9000-9010/udp");
+        assert_eq!(binding.host_port_end, Some(8010));
+        assert_eq!(binding.container_port_end, Some(9010));
+        assert_eq!(binding.port_count(), 11);
+        assert_eq!(binding.to_string(), "8000-8010:9000-9010/udp");
+    }
+
+    #[test]
+    fn port_binding_round_trips_auto_host_port() {
+        let binding = round_trip("auto:80");
+        assert_eq!(binding.host_port, 0);
+        assert_eq!(binding.to_string(), "0:80/tcp");
+    }
+
+    #[test]
+    fn port_binding_rejects_mismatched_range_sizes() {
+        assert!("8000-8010:9000-9005".parse::<PortBinding>().is_err());
+        assert!("8000-8010:9000".parse::<PortBinding>().is_err());
+    }
+
+    #[test]
+    fn port_binding_rejects_out_of_range_ports() {
+        assert!("70000:80".parse::<PortBinding>().is_err());
+        assert!("8080:65536".parse::<PortBinding>().is_err());
+    }
+
+    #[test]
+    fn parse_byte_size_handles_suffixes() {
+        assert_eq!(parse_byte_size("100").unwrap(), 100);
+        assert_eq!(parse_byte_size("100b").unwrap(), 100);
+        assert_eq!(parse_byte_size("2k").unwrap(), 2048);
+        assert_eq!(parse_byte_size("512m").unwrap(), 512 * 1024 * 1024);
+        assert_eq!(parse_byte_size(" 2G ").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_byte_size("1.5k").unwrap(), 1536);
+    }
+
+    #[test]
+    fn parse_byte_size_rejects_invalid_sizes() {
+        assert!(parse_byte_size("").is_err());
+        assert!(parse_byte_size("m").is_err());
+        assert!(parse_byte_size("12x").is_err());
+        assert!(parse_byte_size("-1m").is_err());
+    }
 }
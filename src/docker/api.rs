@@ -0,0 +1,553 @@
+//! A minimal client for the parts of the Docker Engine API that shiplift doesn't support yet
+//!
+//! Everything that shiplift *does* support should still go through shiplift.
+//!
+//! Requests are sent to the Docker-compatible API socket given by the caller, which is normally
+//! the socket of the charm's container runtime. Use the `ContainerRuntime` methods instead of
+//! calling these functions directly.
+
+use anyhow::{format_err, Context};
+use futures::{future::Either, prelude::*};
+use hyper::{Body, Client, Method, Request, StatusCode};
+use hyperlocal::{UnixConnector, Uri};
+use serde_json::{json, Value as JsonValue};
+use shiplift::tty::StreamType;
+
+use std::collections::HashMap;
+
+use super::ContainerCreateOptions;
+use crate::rt::block_on;
+
+/// Make a request to the Docker API and return the parsed JSON response
+///
+/// Responses with an empty body will be returned as `JsonValue::Null`.
+pub(crate) fn request(
+    socket_path: &str,
+    method: Method,
+    endpoint: &str,
+    body: Option<&JsonValue>,
+) -> anyhow::Result<JsonValue> {
+    let (status, response) = send(socket_path, method.clone(), endpoint, body)?;
+
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(api_error(status, &method, endpoint, &response))
+    }
+}
+
+/// Send a request to the Docker API and return the response status and parsed JSON body
+fn send(
+    socket_path: &str,
+    method: Method,
+    endpoint: &str,
+    body: Option<&JsonValue>,
+) -> anyhow::Result<(StatusCode, JsonValue)> {
+    let body = match body {
+        Some(body) => Body::from(serde_json::to_vec(body)?),
+        None => Body::empty(),
+    };
+    let (status, response_body) = send_raw(
+        socket_path,
+        method.clone(),
+        endpoint,
+        "application/json",
+        body,
+    )?;
+
+    // Parse the response
+    let response: JsonValue = if response_body.is_empty() {
+        JsonValue::Null
+    } else {
+        serde_json::from_slice(&response_body).context(format!(
+            "Could not parse Docker API response: {} {}",
+            method, endpoint
+        ))?
+    };
+
+    Ok((status, response))
+}
+
+/// Send a request to the Docker API and return the response status and raw body
+fn send_raw(
+    socket_path: &str,
+    method: Method,
+    endpoint: &str,
+    content_type: &str,
+    body: Body,
+) -> anyhow::Result<(StatusCode, Vec<u8>)> {
+    let client = Client::builder()
+        .keep_alive(false)
+        .build::<_, Body>(UnixConnector::new());
+
+    // Build the request
+    let request = Request::builder()
+        .method(method.clone())
+        .uri(hyper::Uri::from(Uri::new(socket_path, endpoint)))
+        .header("Content-Type", content_type)
+        .body(body)
+        .context("Could not build Docker API request")?;
+
+    // Send the request and collect the response body
+    let (status, response_body) = block_on(client.request(request).and_then(|response| {
+        let status = response.status();
+        response
+            .into_body()
+            .concat2()
+            .map(move |body| (status, body))
+    }))
+    .context(format!(
+        "Docker API request failed: {} {}",
+        method, endpoint
+    ))?;
+
+    Ok((status, response_body.to_vec()))
+}
+
+/// Create an error from an unsuccessful Docker API response that may not be JSON
+fn raw_api_error(
+    status: StatusCode,
+    method: &Method,
+    endpoint: &str,
+    response: &[u8],
+) -> anyhow::Error {
+    let response = serde_json::from_slice(response).unwrap_or(JsonValue::Null);
+    api_error(status, method, endpoint, &response)
+}
+
+/// Percent-encode a value for use in a URL query string
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Create an error from an unsuccessful Docker API response
+fn api_error(
+    status: StatusCode,
+    method: &Method,
+    endpoint: &str,
+    response: &JsonValue,
+) -> anyhow::Error {
+    format_err!(
+        "Docker API error ({}) for {} {}: {}",
+        status,
+        method,
+        endpoint,
+        response["message"].as_str().unwrap_or("unknown error")
+    )
+}
+
+/// List all containers, including stopped containers, that match a Docker API filter
+///
+/// For example a `filter` of `label` with a `value` of `key=value` lists the containers with that
+/// label, and a `filter` of `name` with a `value` of `^/app_` lists containers whose name starts
+/// with `app_`.
+pub(crate) fn list_containers(
+    socket_path: &str,
+    filter: &str,
+    value: &str,
+) -> anyhow::Result<Vec<JsonValue>> {
+    let filters = json!({ filter: [value] }).to_string();
+    let response = request(
+        socket_path,
+        Method::GET,
+        &format!(
+            "/containers/json?all=true&filters={}",
+            encode_query_value(&filters)
+        ),
+        None,
+    )?;
+
+    Ok(response.as_array().cloned().unwrap_or_default())
+}
+
+/// Forcefully remove a container, stopping it if it is running
+pub(crate) fn force_remove_container(socket_path: &str, container_id: &str) -> anyhow::Result<()> {
+    request(
+        socket_path,
+        Method::DELETE,
+        &format!("/containers/{}?force=true", container_id),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Inspect a container, returning `None` if the container does not exist
+pub(crate) fn inspect_container(
+    socket_path: &str,
+    container_id: &str,
+) -> anyhow::Result<Option<JsonValue>> {
+    let endpoint = format!("/containers/{}/json", container_id);
+    let (status, response) = send(socket_path, Method::GET, &endpoint, None)?;
+
+    if status.is_success() {
+        Ok(Some(response))
+    } else if status == StatusCode::NOT_FOUND {
+        Ok(None)
+    } else {
+        Err(api_error(status, &Method::GET, &endpoint, &response))
+    }
+}
+
+/// Inspect an image, returning `None` if the image does not exist
+pub(crate) fn inspect_image(socket_path: &str, image: &str) -> anyhow::Result<Option<JsonValue>> {
+    let endpoint = format!("/images/{}/json", image);
+    let (status, response) = send(socket_path, Method::GET, &endpoint, None)?;
+
+    if status.is_success() {
+        Ok(Some(response))
+    } else if status == StatusCode::NOT_FOUND {
+        Ok(None)
+    } else {
+        Err(api_error(status, &Method::GET, &endpoint, &response))
+    }
+}
+
+/// Remove an image, returning `false` if the image is still in use by a container or has multiple
+/// tags and could not be removed
+pub(crate) fn remove_image(socket_path: &str, image: &str) -> anyhow::Result<bool> {
+    let endpoint = format!("/images/{}", image);
+    let (status, response) = send(socket_path, Method::DELETE, &endpoint, None)?;
+
+    if status.is_success() || status == StatusCode::NOT_FOUND {
+        Ok(true)
+    } else if status == StatusCode::CONFLICT {
+        Ok(false)
+    } else {
+        Err(api_error(status, &Method::DELETE, &endpoint, &response))
+    }
+}
+
+/// Get the digest of a pulled image, such as `sha256:...`
+///
+/// Returns `None` if the image doesn't exist or was not pulled from a registry, such as images that
+/// were built locally.
+pub(crate) fn image_digest(socket_path: &str, image: &str) -> anyhow::Result<Option<String>> {
+    let details = match inspect_image(socket_path, image)? {
+        Some(details) => details,
+        None => return Ok(None),
+    };
+    let repository = super::image_repository(image);
+
+    // Get the repo digests in the form `repository@sha256:...`
+    let repo_digests: Vec<&str> = details["RepoDigests"]
+        .as_array()
+        .map(|digests| digests.iter().filter_map(JsonValue::as_str).collect())
+        .unwrap_or_default();
+
+    // Prefer the digest for the image's repository
+    Ok(repo_digests
+        .iter()
+        .find(|digest| digest.starts_with(&format!("{}@", repository)))
+        .or_else(|| repo_digests.first())
+        .and_then(|digest| digest.split('@').nth(1))
+        .map(ToOwned::to_owned))
+}
+
+/// Create a container and return its ID
+pub(crate) fn create_container(
+    socket_path: &str,
+    options: &ContainerCreateOptions,
+) -> anyhow::Result<String> {
+    let endpoint = match &options.name {
+        Some(name) => format!("/containers/create?name={}", name),
+        None => "/containers/create".into(),
+    };
+
+    let response = request(socket_path, Method::POST, &endpoint, Some(&options.body))?;
+    let container_id = response["Id"]
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| format_err!("Docker API did not return an ID for the created container"))?;
+
+    // Connect the container to any additional networks
+    for connection in &options.network_connections {
+        if let Err(e) = connect_network(
+            socket_path,
+            &connection.network,
+            &container_id,
+            &connection.aliases,
+        ) {
+            // Remove the container so that it isn't orphaned
+            if let Err(remove_error) = force_remove_container(socket_path, &container_id) {
+                log::error!(
+                    "Could not remove container after failing to connect it to a network: {:?}",
+                    remove_error
+                );
+            }
+
+            return Err(e.context(format!(
+                "Could not connect container to network: {}",
+                connection.network
+            )));
+        }
+    }
+
+    Ok(container_id)
+}
+
+/// Create an exec instance that will run a command in a container and return its ID
+pub(crate) fn create_exec(
+    socket_path: &str,
+    container_id: &str,
+    cmd: &[String],
+    env: &[String],
+) -> anyhow::Result<String> {
+    let response = request(
+        socket_path,
+        Method::POST,
+        &format!("/containers/{}/exec", container_id),
+        Some(&json!({
+            "AttachStdout": true,
+            "AttachStderr": true,
+            "Cmd": cmd,
+            "Env": env,
+        })),
+    )?;
+
+    response["Id"]
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| format_err!("Docker API did not return an ID for the exec instance"))
+}
+
+/// Start an exec instance and wait for its command to exit
+///
+/// `on_output` is called with each piece of output as it is received, along with the stream that
+/// it was written to.
+pub(crate) fn start_exec<F>(
+    socket_path: &str,
+    exec_id: &str,
+    mut on_output: F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(StreamType, &[u8]) + Send + 'static,
+{
+    let client = Client::builder()
+        .keep_alive(false)
+        .build::<_, Body>(UnixConnector::new());
+    let endpoint = format!("/exec/{}/start", exec_id);
+
+    // Build the request
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(hyper::Uri::from(Uri::new(socket_path, &endpoint)))
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({ "Detach": false, "Tty": false }).to_string(),
+        ))
+        .context("Could not build Docker API request")?;
+
+    // Send the request and read the output until the stream is closed
+    let (status, response_body) = block_on(client.request(request).and_then(move |response| {
+        let status = response.status();
+
+        if status.is_success() {
+            // The output is multiplexed into frames that start with an 8 byte header containing
+            // the stream type and the size of the frame. Frames may be split across chunks.
+            let mut buffer = Vec::new();
+            Either::A(
+                response
+                    .into_body()
+                    .for_each(move |chunk| {
+                        buffer.extend_from_slice(&chunk);
+                        while buffer.len() >= 8 {
+                            let size =
+                                u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]])
+                                    as usize;
+                            if buffer.len() < 8 + size {
+                                break;
+                            }
+                            let stream_type = match buffer[0] {
+                                0 => StreamType::StdIn,
+                                2 => StreamType::StdErr,
+                                _ => StreamType::StdOut,
+                            };
+                            on_output(stream_type, &buffer[8..8 + size]);
+                            buffer.drain(..8 + size);
+                        }
+
+                        Ok(())
+                    })
+                    .map(move |_| (status, Vec::new())),
+            )
+        } else {
+            Either::B(
+                response
+                    .into_body()
+                    .concat2()
+                    .map(move |body| (status, body.to_vec())),
+            )
+        }
+    }))
+    .context(format!("Docker API request failed: POST {}", endpoint))?;
+
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(raw_api_error(
+            status,
+            &Method::POST,
+            &endpoint,
+            &response_body,
+        ))
+    }
+}
+
+/// Get the exit code of an exec instance whose command has exited
+pub(crate) fn exec_exit_code(socket_path: &str, exec_id: &str) -> anyhow::Result<i64> {
+    let response = request(
+        socket_path,
+        Method::GET,
+        &format!("/exec/{}/json", exec_id),
+        None,
+    )?;
+
+    response["ExitCode"]
+        .as_i64()
+        .ok_or_else(|| format_err!("Docker API did not return an exit code for the exec instance"))
+}
+
+/// Wait for a container to exit and return its exit code
+pub(crate) fn wait_container(socket_path: &str, container_id: &str) -> anyhow::Result<i64> {
+    let response = request(
+        socket_path,
+        Method::POST,
+        &format!("/containers/{}/wait", container_id),
+        None,
+    )?;
+
+    response["StatusCode"]
+        .as_i64()
+        .ok_or_else(|| format_err!("Docker API did not return an exit code for the container"))
+}
+
+/// Get a snapshot of a container's resource usage stats
+pub(crate) fn container_stats(socket_path: &str, container_id: &str) -> anyhow::Result<JsonValue> {
+    request(
+        socket_path,
+        Method::GET,
+        &format!("/containers/{}/stats?stream=false", container_id),
+        None,
+    )
+}
+
+/// Create a bridge network and return its ID
+pub(crate) fn create_network(
+    socket_path: &str,
+    name: &str,
+    labels: &HashMap<String, String>,
+    internal: bool,
+    subnet: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut body = json!({
+        "Name": name,
+        "Driver": "bridge",
+        "CheckDuplicate": true,
+        "Internal": internal,
+        "Labels": labels,
+    });
+    if let Some(subnet) = subnet {
+        body["IPAM"] = json!({ "Config": [{ "Subnet": subnet }] });
+    }
+
+    let response = request(socket_path, Method::POST, "/networks/create", Some(&body))?;
+
+    response["Id"]
+        .as_str()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| format_err!("Docker API did not return an ID for the created network"))
+}
+
+/// Remove a network
+pub(crate) fn remove_network(socket_path: &str, network: &str) -> anyhow::Result<()> {
+    request(
+        socket_path,
+        Method::DELETE,
+        &format!("/networks/{}", network),
+        None,
+    )?;
+
+    Ok(())
+}
+
+/// Connect a container to a network with the given aliases
+pub(crate) fn connect_network(
+    socket_path: &str,
+    network: &str,
+    container_id: &str,
+    aliases: &[String],
+) -> anyhow::Result<()> {
+    request(
+        socket_path,
+        Method::POST,
+        &format!("/networks/{}/connect", network),
+        Some(&json!({
+            "Container": container_id,
+            "EndpointConfig": { "Aliases": aliases },
+        })),
+    )?;
+
+    Ok(())
+}
+
+/// Extract a tar archive into a directory in a container
+pub(crate) fn put_archive(
+    socket_path: &str,
+    container_id: &str,
+    path: &str,
+    archive: Vec<u8>,
+) -> anyhow::Result<()> {
+    let endpoint = format!(
+        "/containers/{}/archive?path={}",
+        container_id,
+        encode_query_value(path)
+    );
+    let (status, response) = send_raw(
+        socket_path,
+        Method::PUT,
+        &endpoint,
+        "application/x-tar",
+        Body::from(archive),
+    )?;
+
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(raw_api_error(status, &Method::PUT, &endpoint, &response))
+    }
+}
+
+/// Get a tar archive of a file or directory in a container
+pub(crate) fn get_archive(
+    socket_path: &str,
+    container_id: &str,
+    path: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let endpoint = format!(
+        "/containers/{}/archive?path={}",
+        container_id,
+        encode_query_value(path)
+    );
+    let (status, response) = send_raw(
+        socket_path,
+        Method::GET,
+        &endpoint,
+        "application/json",
+        Body::empty(),
+    )?;
+
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(raw_api_error(status, &Method::GET, &endpoint, &response))
+    }
+}
@@ -0,0 +1,557 @@
+//! Container runtimes that Lucky can run its containers with
+//!
+//! Every runtime is accessed through its Docker-compatible API socket, so the default
+//! implementations of the `ContainerRuntime` operations use the Docker API. Runtimes only need to
+//! provide the parts that differ, such as how the runtime is installed.
+
+use anyhow::{bail, Context};
+use chrono::{TimeZone, Utc};
+use futures::prelude::*;
+use serde_json::Value as JsonValue;
+use shiplift::{
+    builder::RegistryAuth, tty::StreamType, BuildOptions, Docker, LogsOptions, PullOptions,
+};
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{
+    mpsc::{channel, Receiver},
+    Arc,
+};
+use std::time::Duration;
+
+use super::{api, ContainerCreateOptions, ProxySettings};
+use crate::process::{cmd_exists, run_cmd, run_cmd_with_retries};
+use crate::rt::block_on;
+use crate::types::{ContainerRuntimeType, DockerInstallMethod, LuckyMetadata};
+
+/// The path to the Docker daemon socket
+pub(crate) const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+/// The path to Podman's Docker-compatible API socket
+pub(crate) const PODMAN_SOCKET: &str = "/run/podman/podman.sock";
+/// The path to the Docker daemon config file
+const DOCKER_DAEMON_CONFIG: &str = "/etc/docker/daemon.json";
+
+/// A handler for the output of a command run in a container. It is given the stream that the
+/// output was written to and the output.
+pub(crate) type OutputHandler = Box<dyn FnMut(StreamType, &[u8]) + Send>;
+
+/// A line of output from a container's logs
+pub(crate) struct ContainerLogLine {
+    /// The line of text without the trailing newline
+    pub text: String,
+    /// Whether the line was written to stderr instead of stdout
+    pub stderr: bool,
+}
+
+/// Options for getting a container's logs
+pub(crate) struct ContainerLogOptions {
+    /// Keep streaming new logs as they are written
+    pub follow: bool,
+    /// Only get this many lines from the end of the logs
+    pub tail: Option<u64>,
+    /// Only get logs written after this Unix timestamp
+    pub since: Option<i64>,
+    /// Include lines written to stdout
+    pub stdout: bool,
+    /// Include lines written to stderr
+    pub stderr: bool,
+}
+
+/// A container runtime that Lucky can manage containers with
+pub(crate) trait ContainerRuntime: Send + Sync {
+    /// The name of the runtime, used in log and status messages
+    fn name(&self) -> &'static str;
+
+    /// The path to the runtime's Docker-compatible API socket
+    fn socket_path(&self) -> &'static str;
+
+    /// Install the runtime if it is not already installed and make sure that it is running
+    fn ensure_installed(&self) -> anyhow::Result<()>;
+
+    /// Set the proxy settings that the runtime uses to pull images, restarting the runtime if it
+    /// is running and the settings changed
+    ///
+    /// The runtime's name is used as the name of its systemd service.
+    fn configure_proxy(&self, proxy_settings: &ProxySettings) -> anyhow::Result<()> {
+        configure_service_proxy(self.name(), proxy_settings)
+    }
+
+    /// Get a shiplift client connected to the runtime's API socket
+    fn connect(&self) -> Docker {
+        Docker::unix(self.socket_path())
+    }
+
+    /// Create a container and return its ID
+    fn create_container(&self, options: &ContainerCreateOptions) -> anyhow::Result<String> {
+        api::create_container(self.socket_path(), options)
+    }
+
+    /// Start a container
+    fn start_container(&self, container_id: &str) -> anyhow::Result<()> {
+        block_on(self.connect().containers().get(container_id).start())?;
+        Ok(())
+    }
+
+    /// Stop a container, killing it if it hasn't stopped after `timeout`
+    fn stop_container(&self, container_id: &str, timeout: Duration) -> anyhow::Result<()> {
+        block_on(
+            self.connect()
+                .containers()
+                .get(container_id)
+                .stop(Some(timeout)),
+        )?;
+        Ok(())
+    }
+
+    /// Remove a container. If `force` is `true` the container will be killed if it is running.
+    fn remove_container(&self, container_id: &str, force: bool) -> anyhow::Result<()> {
+        if force {
+            api::force_remove_container(self.socket_path(), container_id)
+        } else {
+            block_on(self.connect().containers().get(container_id).delete())?;
+            Ok(())
+        }
+    }
+
+    /// Run a command in a running container and return its exit code
+    ///
+    /// The output of the command is passed to `on_output` as it is received.
+    fn exec(
+        &self,
+        container_id: &str,
+        cmd: &[String],
+        env: &[String],
+        on_output: OutputHandler,
+    ) -> anyhow::Result<i64> {
+        let exec_id = api::create_exec(self.socket_path(), container_id, cmd, env)?;
+        api::start_exec(self.socket_path(), &exec_id, on_output)?;
+
+        // Get the exit code of the command now that it has exited
+        api::exec_exit_code(self.socket_path(), &exec_id)
+            .context("Could not get the exit code of the command")
+    }
+
+    /// Pull an image
+    fn pull_image(&self, image: &str, auth: Option<RegistryAuth>) -> anyhow::Result<()> {
+        let mut pull_options = PullOptions::builder();
+        pull_options.image(image);
+        if let Some(auth) = auth {
+            pull_options.auth(auth);
+        }
+        block_on(
+            self.connect()
+                .images()
+                .pull(&pull_options.build())
+                .collect(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Inspect a container, returning `None` if the container does not exist
+    fn inspect_container(&self, container_id: &str) -> anyhow::Result<Option<JsonValue>> {
+        api::inspect_container(self.socket_path(), container_id)
+    }
+
+    /// Inspect an image, returning `None` if the image does not exist
+    fn inspect_image(&self, image: &str) -> anyhow::Result<Option<JsonValue>> {
+        api::inspect_image(self.socket_path(), image)
+    }
+
+    /// Remove an image, returning `false` if the image is in use and could not be removed
+    fn remove_image(&self, image: &str) -> anyhow::Result<bool> {
+        api::remove_image(self.socket_path(), image)
+    }
+
+    /// Get a snapshot of a container's resource usage stats
+    fn container_stats(&self, container_id: &str) -> anyhow::Result<JsonValue> {
+        api::container_stats(self.socket_path(), container_id)
+    }
+
+    /// List all containers, including stopped containers, that match a Docker API filter, such
+    /// as a `label` filter with a value of `key=value`
+    fn list_containers(&self, filter: &str, value: &str) -> anyhow::Result<Vec<JsonValue>> {
+        api::list_containers(self.socket_path(), filter, value)
+    }
+
+    /// Wait for a container to exit and return its exit code
+    fn wait_container(&self, container_id: &str) -> anyhow::Result<i64> {
+        api::wait_container(self.socket_path(), container_id)
+    }
+
+    /// Get the digest of a pulled image, or `None` if the image was not pulled from a registry
+    fn image_digest(&self, image: &str) -> anyhow::Result<Option<String>> {
+        api::image_digest(self.socket_path(), image)
+    }
+
+    /// Build an image from a context directory, tag it, and return its ID
+    ///
+    /// The output of the build is logged at the debug level.
+    fn build_image(&self, context_dir: &Path, tag: &str) -> anyhow::Result<String> {
+        let docker_conn = self.connect();
+        let images = docker_conn.images();
+
+        // Build the image
+        let build_output = block_on(
+            images
+                .build(
+                    &BuildOptions::builder(context_dir.to_string_lossy())
+                        .tag(tag)
+                        .build(),
+                )
+                .collect(),
+        )?;
+        for output in build_output {
+            if let Some(message) = output["stream"].as_str() {
+                log::debug!("{}", message.trim_end());
+            }
+            if let Some(error) = output["error"].as_str() {
+                bail!("{}", error);
+            }
+        }
+
+        // Get the ID of the built image
+        Ok(block_on(images.get(tag).inspect())
+            .context(format!("Could not inspect built image: {}", tag))?
+            .id)
+    }
+
+    /// Get the logs of a container
+    ///
+    /// The log lines are sent over the returned channel as they are recieved from the runtime.
+    /// When following the logs, the channel will stay open until the container is removed or the
+    /// receiver is dropped.
+    fn container_logs(
+        &self,
+        container_id: &str,
+        options: &ContainerLogOptions,
+    ) -> anyhow::Result<Receiver<ContainerLogLine>> {
+        let docker_conn = self.connect();
+        let containers = docker_conn.containers();
+        let container = containers.get(container_id);
+
+        // Build the log options
+        let mut log_options = LogsOptions::builder();
+        log_options
+            .follow(options.follow)
+            .stdout(options.stdout)
+            .stderr(options.stderr);
+        if let Some(tail) = options.tail {
+            log_options.tail(&tail.to_string());
+        }
+        if let Some(since) = options.since {
+            log_options.since(&Utc.timestamp(since, 0));
+        }
+
+        // Stream the logs over the channel in the background
+        let (sender, receiver) = channel();
+        crate::rt::spawn(
+            container
+                .logs(&log_options.build())
+                .for_each(move |chunk| {
+                    let stderr = matches!(chunk.stream_type, StreamType::StdErr);
+
+                    for line in chunk.as_string_lossy().lines() {
+                        sender
+                            .send(ContainerLogLine {
+                                text: line.into(),
+                                stderr,
+                            })
+                            .map_err(|_| {
+                                shiplift::Error::InvalidResponse("Log receiver disconnected".into())
+                            })?;
+                    }
+
+                    Ok(())
+                })
+                .map_err(|e| log::debug!("Container log stream closed: {}", e)),
+        );
+
+        Ok(receiver)
+    }
+
+    /// Extract a tar archive into a directory in a container
+    fn put_archive(&self, container_id: &str, path: &str, archive: Vec<u8>) -> anyhow::Result<()> {
+        api::put_archive(self.socket_path(), container_id, path, archive)
+    }
+
+    /// Get a tar archive of a file or directory in a container
+    fn get_archive(&self, container_id: &str, path: &str) -> anyhow::Result<Vec<u8>> {
+        api::get_archive(self.socket_path(), container_id, path)
+    }
+
+    /// Create a bridge network and return its ID
+    fn create_network(
+        &self,
+        name: &str,
+        labels: &HashMap<String, String>,
+        internal: bool,
+        subnet: Option<&str>,
+    ) -> anyhow::Result<String> {
+        api::create_network(self.socket_path(), name, labels, internal, subnet)
+    }
+
+    /// Remove a network
+    fn remove_network(&self, network: &str) -> anyhow::Result<()> {
+        api::remove_network(self.socket_path(), network)
+    }
+
+    /// Remove a volume
+    fn remove_volume(&self, volume: &str) -> anyhow::Result<()> {
+        api::request(
+            self.socket_path(),
+            hyper::Method::DELETE,
+            &format!("/volumes/{}", volume),
+            None,
+        )?;
+        Ok(())
+    }
+}
+
+/// Get the container runtime selected in the charm's `lucky.yaml`
+pub(crate) fn get_runtime(lucky_metadata: &LuckyMetadata) -> Arc<dyn ContainerRuntime> {
+    match lucky_metadata.container_runtime {
+        ContainerRuntimeType::Docker => Arc::new(DockerRuntime {
+            install_method: lucky_metadata.docker_settings.install.clone(),
+        }),
+        ContainerRuntimeType::Podman => Arc::new(PodmanRuntime),
+    }
+}
+
+/// The Docker container runtime
+pub(crate) struct DockerRuntime {
+    /// How to install Docker
+    install_method: DockerInstallMethod,
+}
+
+impl ContainerRuntime for DockerRuntime {
+    fn name(&self) -> &'static str {
+        "docker"
+    }
+
+    fn socket_path(&self) -> &'static str {
+        DOCKER_SOCKET
+    }
+
+    fn ensure_installed(&self) -> anyhow::Result<()> {
+        match &self.install_method {
+            DockerInstallMethod::Skip => {
+                unhold_docker_package()?;
+
+                // Make sure that Docker is actually present
+                if !cmd_exists("docker", &["--version"])? {
+                    bail!("Docker is not installed and the Docker install method is `skip`");
+                }
+                return Ok(());
+            }
+            DockerInstallMethod::Package => {
+                unhold_docker_package()?;
+
+                // Skip if docker is already installed
+                if cmd_exists("docker", &["--version"])? {
+                    return Ok(());
+                };
+
+                // TODO: We will also want to support Centos, but we might just have to install
+                // different packages depending on the system.
+                run_cmd_with_retries(
+                    "apt-get",
+                    &["install", "-y", "docker.io"],
+                    &Default::default(),
+                )?;
+            }
+            DockerInstallMethod::Version(version) => {
+                // Skip if the requested version is already installed
+                if installed_package_version("docker.io").as_ref() == Some(version) {
+                    return Ok(());
+                }
+
+                // Install the requested version and hold it so that it isn't upgraded
+                run_cmd_with_retries(
+                    "apt-get",
+                    &[
+                        "install",
+                        "-y",
+                        "--allow-downgrades",
+                        &format!("docker.io={}", version),
+                    ],
+                    &Default::default(),
+                )?;
+                run_cmd("apt-mark", &["hold", "docker.io"])?;
+            }
+        }
+
+        // Make sure docker is installed
+        if !cmd_exists("docker", &["--version"])? {
+            bail!("Could not install Docker");
+        }
+
+        Ok(())
+    }
+}
+
+/// The Podman container runtime, used through Podman's Docker-compatible API service
+pub(crate) struct PodmanRuntime;
+
+impl ContainerRuntime for PodmanRuntime {
+    fn name(&self) -> &'static str {
+        "podman"
+    }
+
+    fn socket_path(&self) -> &'static str {
+        PODMAN_SOCKET
+    }
+
+    fn ensure_installed(&self) -> anyhow::Result<()> {
+        // Install Podman if it isn't already installed
+        if !cmd_exists("podman", &["--version"])? {
+            run_cmd_with_retries("apt-get", &["install", "-y", "podman"], &Default::default())?;
+            // Make sure podman is installed
+            if !cmd_exists("podman", &["--version"])? {
+                bail!("Could not install Podman");
+            }
+        }
+
+        // Start the Docker-compatible API service. The socket will start the service on demand.
+        run_cmd("systemctl", &["enable", "--now", "podman.socket"])?;
+
+        Ok(())
+    }
+}
+
+/// Release the hold that the `version` install method puts on the `docker.io` package, if it is
+/// held, so that the package can be upgraded again after switching to another install method
+fn unhold_docker_package() -> anyhow::Result<()> {
+    // Skip hosts that don't use apt
+    if !cmd_exists("apt-mark", &["--version"])? {
+        return Ok(());
+    }
+
+    let held_packages = run_cmd("apt-mark", &["showhold"])?;
+    if held_packages
+        .lines()
+        .any(|package| package.trim() == "docker.io")
+    {
+        log::info!("Releasing the hold on the docker.io package");
+        run_cmd("apt-mark", &["unhold", "docker.io"])?;
+    }
+
+    Ok(())
+}
+
+/// Get the installed version of a Debian package, or `None` if it is not installed
+fn installed_package_version(package: &str) -> Option<String> {
+    run_cmd(
+        "dpkg-query",
+        &["--show", "--showformat=${Version}", package],
+    )
+    .ok()
+    .map(|version| version.trim().to_string())
+    .filter(|version| !version.is_empty())
+}
+
+/// Set the given settings in Docker's `daemon.json`, restarting Docker if the file changed
+///
+/// `settings` must be a JSON object. Keys set to `null` will be removed from the file and keys that
+/// are not in `settings` are left alone. Returns `true` if the file was changed.
+pub(crate) fn configure_docker_daemon(settings: &JsonValue) -> anyhow::Result<bool> {
+    let settings = settings
+        .as_object()
+        .context("Docker daemon settings must be a JSON object")?;
+
+    // Load the current config
+    let old_config: JsonValue = match fs::read_to_string(DOCKER_DAEMON_CONFIG) {
+        Ok(contents) if !contents.trim().is_empty() => serde_json::from_str(&contents)
+            .context(format!("Could not parse {}", DOCKER_DAEMON_CONFIG))?,
+        Ok(_) => JsonValue::Object(Default::default()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => JsonValue::Object(Default::default()),
+        Err(e) => {
+            return Err(e).context(format!("Could not read {}", DOCKER_DAEMON_CONFIG));
+        }
+    };
+
+    // Apply the settings
+    let mut new_config = old_config.clone();
+    let config_map = new_config
+        .as_object_mut()
+        .context(format!("{} is not a JSON object", DOCKER_DAEMON_CONFIG))?;
+    for (key, value) in settings {
+        if value.is_null() {
+            config_map.remove(key);
+        } else {
+            config_map.insert(key.clone(), value.clone());
+        }
+    }
+
+    // Skip if nothing has changed
+    if new_config == old_config {
+        return Ok(false);
+    }
+
+    // Write the new config
+    log::info!("Updating Docker daemon config: {}", DOCKER_DAEMON_CONFIG);
+    fs::create_dir_all("/etc/docker").context("Could not create Docker config dir")?;
+    fs::write(
+        DOCKER_DAEMON_CONFIG,
+        format!("{}\n", serde_json::to_string_pretty(&new_config)?),
+    )
+    .context(format!("Could not write {}", DOCKER_DAEMON_CONFIG))?;
+
+    // Restart Docker to apply the changes
+    run_cmd("systemctl", &["restart", "docker"])?;
+
+    Ok(true)
+}
+
+/// Write the proxy settings to a systemd drop-in for a runtime's service
+///
+/// The drop-in is removed if there aren't any proxy settings. If the drop-in changed, the service
+/// is restarted if it is running.
+fn configure_service_proxy(service: &str, proxy_settings: &ProxySettings) -> anyhow::Result<()> {
+    let dropin_dir = format!("/etc/systemd/system/{}.service.d/", service);
+    let file_path = format!("{}http-proxy.conf", dropin_dir);
+
+    // Create the drop-in contents
+    let mut proxy_env = String::new();
+    for (var, value) in proxy_settings.env_vars() {
+        proxy_env.push_str(&format!("Environment=\"{}={}\"\n", var, value));
+    }
+    let new_contents = if proxy_env.is_empty() {
+        None
+    } else {
+        Some(format!("[Service]\n{}", proxy_env))
+    };
+
+    // Skip if the drop-in is already up-to-date
+    let old_contents = fs::read_to_string(&file_path).ok();
+    if old_contents == new_contents {
+        return Ok(());
+    }
+
+    if let Some(contents) = new_contents {
+        // Write the drop-in
+        fs::create_dir_all(&dropin_dir).context(format!(
+            "Could not create {} service drop-in config dir: {:?}",
+            service, dropin_dir
+        ))?;
+        fs::write(&file_path, contents).context(format!(
+            "Could not write {} service dropin file: {:?}",
+            service, file_path
+        ))?;
+    } else {
+        // Remove the drop-in
+        fs::remove_file(&file_path).context(format!(
+            "Could not remove {} service dropin file: {:?}",
+            service, file_path
+        ))?;
+    }
+
+    // Reload the service config and restart the service if it is running
+    log::info!("Updating {} proxy settings", service);
+    run_cmd("systemctl", &["daemon-reload"])?;
+    run_cmd("systemctl", &["try-restart", service])?;
+
+    Ok(())
+}
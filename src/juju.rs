@@ -233,3 +233,29 @@ pub(crate) fn juju_log(message: &str, debug: bool) {
         }
     }
 }
+
+/// Log messages to the Juju log from outside of a hook context
+///
+/// This uses `juju-run` to get a hook context to run `juju-log` in, which means that it will block
+/// until any hook that is currently running has exited.
+pub(crate) fn juju_log_outside_hook(
+    unit_name: &str,
+    messages: &[String],
+    debug: bool,
+) -> anyhow::Result<()> {
+    // Build a `juju-log` command for each message, quoting the message for the shell
+    let commands: Vec<String> = messages
+        .iter()
+        .map(|message| {
+            format!(
+                "juju-log {}-- '{}'",
+                if debug { "--debug " } else { "" },
+                message.replace('\'', r"'\''")
+            )
+        })
+        .collect();
+
+    run_cmd("juju-run", &[unit_name, &commands.join("; ")])?;
+
+    Ok(())
+}
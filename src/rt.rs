@@ -3,13 +3,27 @@
 use lazy_static::lazy_static;
 use std::sync::{Arc, Mutex};
 use tokio::prelude::Future;
-use tokio::runtime::Runtime;
+use tokio::runtime::{Runtime, TaskExecutor};
+
+/// The Tokio runtime along with its executor
+struct TokioRuntime {
+    /// The runtime, which is locked while `block_on` is running a future
+    runtime: Mutex<Runtime>,
+    /// The executor for the runtime. This is taken from the runtime when it is created so that
+    /// futures can be spawned without having to wait for the runtime lock held by `block_on`.
+    executor: Mutex<TaskExecutor>,
+}
 
 lazy_static! {
     /// The Tokio runtime used to execute async code
-    static ref RT: Arc<Mutex<Runtime>> = Arc::new(Mutex::new(
-        Runtime::new().expect("Could not start tokio runtime")
-    ));
+    static ref RT: Arc<TokioRuntime> = {
+        let runtime = Runtime::new().expect("Could not start tokio runtime");
+        let executor = runtime.executor();
+        Arc::new(TokioRuntime {
+            runtime: Mutex::new(runtime),
+            executor: Mutex::new(executor),
+        })
+    };
 }
 
 /// Run a future with the tokio executor
@@ -19,6 +33,14 @@ where
     R: Send + 'static,
     E: Send + 'static,
 {
-    let mut rt = RT.lock().unwrap();
+    let mut rt = RT.runtime.lock().unwrap();
     rt.block_on(future)
 }
+
+/// Spawn a future on the tokio executor and run it in the background
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Send + 'static + Future<Item = (), Error = ()>,
+{
+    RT.executor.lock().unwrap().spawn(future);
+}
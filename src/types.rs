@@ -9,11 +9,6 @@ use strum_macros::{AsRefStr, EnumString, EnumVariantNames};
 use crate::rpc::ScriptStatus as RpcScriptStatus;
 use crate::rpc::ScriptStatus_state as RpcScriptState;
 
-/// The prefix for the exit code in the exit code helper command
-///
-/// See `lucky::cli::daemon::exit_code_helper`.
-pub(crate) const LUCKY_EXIT_CODE_HELPER_PREFIX: &str = "__LUCKY_CMD_EXIT_CODE__:";
-
 /// Juju related types
 pub(crate) mod juju;
 
@@ -123,12 +118,234 @@ pub(crate) struct LuckyMetadata {
     #[serde(default = "default_true")]
     /// Specifies whether or not to install Docker on the host and enable Docker-based features
     pub use_docker: bool,
+    /// The container runtime to run the charm's containers with
+    #[serde(default)]
+    pub container_runtime: ContainerRuntimeType,
+    /// Settings for how Docker is installed and configured
+    #[serde(default)]
+    pub docker_settings: DockerSettings,
     /// The hooks for the charm
     #[serde(default)]
     pub hooks: HashMap<String, Vec<CharmScript>>,
     /// The cron jobs for the charm
     #[serde(default)]
     pub cron_jobs: IndexMap<String, Vec<CharmScript>>, // Use an IndexMap to preserve order
+    #[serde(default = "default_false")]
+    /// Specifies whether or not to forward the stderr output of the charm's containers to the
+    /// Juju log
+    pub forward_container_logs: bool,
+    #[serde(default = "default_false")]
+    /// Specifies whether or not to add the Juju model's proxy settings to the environment of the
+    /// charm's containers
+    pub forward_proxy_settings: bool,
+    /// Settings for removing container images that are no longer used by the charm's containers
+    #[serde(default)]
+    pub image_cleanup: ImageCleanupSettings,
+    /// Container images to build from the charm's `container_images` dir
+    #[serde(default)]
+    pub container_images: HashMap<String, ContainerImageBuildConfig>,
+    /// Containers to run for the charm, keyed by container name. The `default` container is the
+    /// charm's default container.
+    #[serde(default)]
+    pub containers: IndexMap<String, ContainerDefinition>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// A container runtime that Lucky can run containers with
+pub(crate) enum ContainerRuntimeType {
+    /// Docker, installed from the `docker.io` package
+    Docker,
+    /// Podman, used through its Docker-compatible API service
+    Podman,
+}
+
+impl Default for ContainerRuntimeType {
+    fn default() -> Self {
+        ContainerRuntimeType::Docker
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// The `docker-settings` section of the `lucky.yaml`
+///
+/// These settings only apply when the container runtime is Docker.
+pub(crate) struct DockerSettings {
+    /// How to install Docker
+    #[serde(default)]
+    pub install: DockerInstallMethod,
+    /// Settings to manage in Docker's `/etc/docker/daemon.json`
+    #[serde(default)]
+    pub daemon_config: DockerDaemonConfig,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+/// How to install Docker
+///
+/// Switching from `Version` to another install method releases the hold on the `docker.io`
+/// package.
+pub(crate) enum DockerInstallMethod {
+    /// Install the distribution's `docker.io` package if Docker is not already installed
+    Package,
+    /// Install and hold the given version of the distribution's `docker.io` package
+    Version(String),
+    /// Don't install Docker and assume that it is already present on the host
+    Skip,
+}
+
+impl Default for DockerInstallMethod {
+    fn default() -> Self {
+        DockerInstallMethod::Package
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// Settings to manage in Docker's `daemon.json`
+///
+/// The fields are named the same as the `daemon.json` keys that they set. Only the settings that
+/// are declared are managed by Lucky. All of the string values may be Handlebars templates that
+/// are rendered with the charm config, and a setting that renders to an empty value will be
+/// removed from the `daemon.json`.
+pub(crate) struct DockerDaemonConfig {
+    /// Registry mirrors to pull images through
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub registry_mirrors: Vec<String>,
+    /// Registries that may be accessed over HTTP or with untrusted certificates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub insecure_registries: Vec<String>,
+    /// The default logging driver for containers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_driver: Option<String>,
+    /// Options for the default logging driver
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub log_opts: HashMap<String, String>,
+    /// The storage driver for images and containers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub storage_driver: Option<String>,
+    /// The address pools that Docker allocates network subnets from
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_address_pools: Vec<DockerAddressPool>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+/// An address pool that Docker allocates network subnets from
+pub(crate) struct DockerAddressPool {
+    /// The pool's address range in CIDR notation, such as `10.10.0.0/16`
+    pub base: String,
+    /// The prefix length of the subnets allocated from the pool
+    pub size: u8,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// The `image-cleanup` section of the `lucky.yaml`
+pub(crate) struct ImageCleanupSettings {
+    /// Whether or not to remove unused images after container updates are applied
+    #[serde(default = "default_false")]
+    pub enabled: bool,
+    /// The number of the most recently used images to keep after they are no longer used, so that
+    /// containers can be rolled back without pulling the image again
+    #[serde(default = "default_keep_last_images")]
+    pub keep_last: usize,
+}
+
+impl Default for ImageCleanupSettings {
+    fn default() -> Self {
+        ImageCleanupSettings {
+            enabled: false,
+            keep_last: default_keep_last_images(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// An image to build from a Dockerfile in the charm's `container_images` dir
+pub(crate) struct ContainerImageBuildConfig {
+    /// The tag to give the built image. Defaults to `lucky/<unit_name>/<image_name>:latest`.
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// The container to use the image for. Defaults to the default container.
+    #[serde(default)]
+    pub container: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// A container declared in the `containers` section of the `lucky.yaml`
+///
+/// All of the string values may be Handlebars templates that are rendered with the charm config,
+/// the key-value store, and relation data before the definition is applied.
+pub(crate) struct ContainerDefinition {
+    /// The container image
+    pub image: String,
+    /// Environment variables to set in the container
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Port bindings in the form `[host_ip:]host_port:container_port[/protocol]`
+    #[serde(default)]
+    pub ports: Vec<String>,
+    /// Whether or not to open the host ports of all of the container's port bindings through Juju
+    #[serde(default)]
+    pub auto_expose: bool,
+    /// Volumes in the form `source:target`
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// The Docker network to run the container in, such as `host`
+    #[serde(default)]
+    pub network: Option<String>,
+    /// The command to run in the container
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// Resource limits, keyed by the limit names used by `lucky container limits`, such as
+    /// `memory` or `ulimit-nofile`. A limit that renders to an empty value is removed.
+    #[serde(default)]
+    pub limits: HashMap<String, String>,
+    /// Containers that are run to completion, in order, before the container is started. Each
+    /// init container must exit successfully for the container to be started.
+    #[serde(default)]
+    pub init: Vec<JobContainerDefinition>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+/// A container that is run to completion, used for job container scripts and init containers
+pub(crate) struct JobContainerDefinition {
+    /// The container image
+    pub image: String,
+    /// Environment variables to set in the container
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// Volumes in the form `source:target`
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// The Docker network to run the container in, such as `host`
+    #[serde(default)]
+    pub network: Option<String>,
+    /// The Lucky networks to attach the container to
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// The container entrypoint
+    #[serde(default)]
+    pub entrypoint: Option<String>,
+    /// The command to run in the container
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    /// The charm config key containing the registry credentials to pull the image with. Only used
+    /// by job containers; init containers use the credentials of the container that they belong
+    /// to.
+    #[serde(default)]
+    pub registry_auth_config_key: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -183,6 +400,11 @@ pub(crate) enum CharmScriptType {
         #[serde(default = "default_false")]
         ignore_missing_container: bool,
     },
+    /// A script that runs a new container to completion and removes it when it exits
+    #[serde(rename_all = "kebab-case")]
+    JobContainer {
+        job_container: JobContainerDefinition,
+    },
 }
 
 //
@@ -197,6 +419,11 @@ fn default_false() -> bool {
     false
 }
 
+/// The default number of unused images to keep
+fn default_keep_last_images() -> usize {
+    1
+}
+
 fn default_shell() -> Vec<String> {
     vec!["/bin/bash".into(), "-c".into()]
 }